@@ -2,7 +2,7 @@ mod input;
 
 use bevy::prelude::*;
 use bevy::pbr::wireframe::{Wireframe, WireframePlugin};
-use bevy::render::mesh::VertexAttributeValues;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
 use bevy::render::render_resource::PrimitiveTopology;
 use std::time::Instant;
 
@@ -11,6 +11,9 @@ const FIELD_WIDTH: usize = 128;
 const FIELD_HEIGHT: usize = 128;
 const FIELD_DEPTH: usize = 128;
 
+// Density at which the surface is extracted
+const ISO_LEVEL: f32 = 0.0;
+
 #[derive(Component)]
 pub struct CameraController {
     yaw: f32,
@@ -101,16 +104,31 @@ fn generate_density(
     (densities, normals)
 }
 
-fn create_mesh(positions: Vec<[f32; 3]>, normals: Vec<[f32; 3]>) -> Mesh {
+fn create_mesh(mut source: meshing::Mesh) -> Mesh {
+    source.triangulate();
+
     let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
     mesh.insert_attribute(
         Mesh::ATTRIBUTE_POSITION,
-        VertexAttributeValues::Float32x3(positions),
+        VertexAttributeValues::Float32x3(source.positions),
     );
     mesh.insert_attribute(
         Mesh::ATTRIBUTE_NORMAL,
-        VertexAttributeValues::Float32x3(normals),
+        VertexAttributeValues::Float32x3(source.normals),
     );
+    if let Some(colors) = source.colors {
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_COLOR,
+            VertexAttributeValues::Float32x4(colors),
+        );
+    }
+    if let Some(uvs) = source.uvs {
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_UV_0,
+            VertexAttributeValues::Float32x2(uvs),
+        );
+    }
+    mesh.set_indices(source.indices.map(Indices::U32));
     mesh
 }
 
@@ -156,21 +174,20 @@ fn setup(
     // Generate isosurface
 
     let (densities, normals) = generate_density(FIELD_WIDTH, FIELD_HEIGHT, FIELD_DEPTH);
+    let field = meshing::DenseField::new(&densities, FIELD_WIDTH, FIELD_HEIGHT, FIELD_DEPTH)
+        .with_normals(&normals);
 
     // Dual contouring
 
     let begin = Instant::now();
-    let (mesh_positions, mesh_normals) = meshing::dual_contouring(
-        &densities,
-        &normals,
-        FIELD_WIDTH,
-        FIELD_HEIGHT,
-        FIELD_DEPTH,
+    let dc_mesh = meshing::dual_contouring(
+        &field,
+        ISO_LEVEL,
     );
     let end = Instant::now();
     println!("DC Time: {:?}", end - begin);
 
-    let mesh = meshes.add(create_mesh(mesh_positions, mesh_normals));
+    let mesh = meshes.add(create_mesh(dc_mesh));
     commands.spawn((
         PbrBundle {
             mesh,
@@ -191,16 +208,14 @@ fn setup(
     // Marching cubes
 
     let begin = Instant::now();
-    let (mesh_positions, mesh_normals) = meshing::marching_cubes(
-        &densities,
-        FIELD_WIDTH,
-        FIELD_HEIGHT,
-        FIELD_DEPTH,
+    let mc_mesh = meshing::marching_cubes(
+        &field,
+        ISO_LEVEL,
     );
     let end = Instant::now();
     println!("MC Time: {:?}", end - begin);
 
-    let mesh = meshes.add(create_mesh(mesh_positions, mesh_normals));
+    let mesh = meshes.add(create_mesh(mc_mesh));
     commands.spawn((
         PbrBundle {
             mesh,
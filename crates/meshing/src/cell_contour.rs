@@ -0,0 +1,201 @@
+use glam::Vec3;
+
+/// Polyhedral cell with samples at its vertices, used to derive marching cubes
+/// style lookup tables
+///
+/// For each sign configuration of the samples the surface is traced as loops
+/// of crossed edges on the boundary of the cell, which are then triangulated.
+/// Ambiguous faces are resolved with a rule that only depends on the face
+/// itself, so neighbouring cells always agree on the contour of their shared
+/// face.
+pub(crate) struct CellShape {
+    /// Sample each sample takes its sign from, samples sharing their sign
+    /// source never have a crossing between them
+    sign_source: Vec<usize>,
+    /// Sample pairs of the edges that can be crossed by the surface
+    edges: Vec<(usize, usize)>,
+    /// Boundary faces as cycles of samples, counter-clockwise seen from outside
+    faces: Vec<Vec<usize>>,
+}
+
+/// Lookup table with one entry per sign configuration
+pub(crate) struct CellTable {
+    /// Triangles as triples of crossed edges, facing the positive samples
+    pub triangles: Vec<Vec<[u8; 3]>>,
+}
+
+impl CellShape {
+    /// `sign_source` must map the independent samples, which come first, to
+    /// themselves. Faces may be given in either orientation.
+    pub fn new(
+        positions: &[Vec3],
+        sign_source: Vec<usize>,
+        edges: Vec<(usize, usize)>,
+        faces: Vec<Vec<usize>>,
+    ) -> Self {
+        let centroid = positions.iter().sum::<Vec3>() / positions.len() as f32;
+        let faces = faces
+            .into_iter()
+            .map(|mut face| {
+                // Newell's method, works for the degenerate polygons of transition cells
+                let mut normal = Vec3::ZERO;
+                let mut center = Vec3::ZERO;
+                for (i, &s) in face.iter().enumerate() {
+                    let next = face[(i + 1) % face.len()];
+                    normal += positions[s].cross(positions[next]);
+                    center += positions[s];
+                }
+                center /= face.len() as f32;
+
+                if normal.dot(center - centroid) < 0.0 {
+                    face.reverse();
+                }
+                face
+            })
+            .collect();
+
+        Self {
+            sign_source,
+            edges,
+            faces,
+        }
+    }
+
+    /// Cube with the given corner offsets
+    ///
+    /// Edges are all corner pairs differing along one axis, in order of the
+    /// first and then second corner.
+    pub fn cube(corners: &[(usize, usize, usize); 8]) -> Self {
+        let positions: Vec<Vec3> = corners
+            .iter()
+            .map(|c| Vec3::new(c.0 as f32, c.1 as f32, c.2 as f32))
+            .collect();
+
+        let mut edges = Vec::new();
+        for a in 0..8 {
+            for b in a + 1..8 {
+                if (positions[a] - positions[b]).length_squared() == 1.0 {
+                    edges.push((a, b));
+                }
+            }
+        }
+
+        let corner = |x, y, z| corners.iter().position(|&c| c == (x, y, z)).unwrap();
+        let mut faces = Vec::new();
+        for side in 0..2 {
+            faces.push(vec![corner(side, 0, 0), corner(side, 1, 0), corner(side, 1, 1), corner(side, 0, 1)]);
+            faces.push(vec![corner(0, side, 0), corner(1, side, 0), corner(1, side, 1), corner(0, side, 1)]);
+            faces.push(vec![corner(0, 0, side), corner(1, 0, side), corner(1, 1, side), corner(0, 1, side)]);
+        }
+
+        Self::new(&positions, (0..8).collect(), edges, faces)
+    }
+
+    pub fn edges(&self) -> &[(usize, usize)] {
+        &self.edges
+    }
+
+    pub fn num_cases(&self) -> usize {
+        let independent = self.sign_source.iter().enumerate().filter(|&(i, &s)| i == s).count();
+        1 << independent
+    }
+
+    fn positive(&self, case: usize, sample: usize) -> bool {
+        case & (1 << self.sign_source[sample]) != 0
+    }
+
+    fn edge(&self, a: usize, b: usize) -> u8 {
+        self.edges
+            .iter()
+            .position(|&e| e == (a, b) || e == (b, a))
+            .expect("sign change between samples without an edge") as u8
+    }
+
+    /// Traces the loops of crossed edges for the sign configuration `case`
+    ///
+    /// Bit `i` of `case` is set if sample `i` is positive. Loops run
+    /// counter-clockwise around the negative region as seen from outside.
+    /// `connect_negative` is called for faces with four crossings and decides
+    /// whether the negative samples of that face are connected.
+    pub fn loops(&self, case: usize, connect_negative: impl Fn(usize) -> bool) -> Vec<Vec<u8>> {
+        let mut next = vec![u8::MAX; self.edges.len()];
+
+        for (f, face) in self.faces.iter().enumerate() {
+            // Crossed edges in counter-clockwise order, and whether the face
+            // enters the negative region at the crossing
+            let mut crossings = Vec::with_capacity(4);
+            for (i, &a) in face.iter().enumerate() {
+                let b = face[(i + 1) % face.len()];
+                let positive = self.positive(case, a);
+                if positive != self.positive(case, b) {
+                    crossings.push((self.edge(a, b) as usize, positive));
+                }
+            }
+
+            if crossings.is_empty() {
+                continue;
+            }
+
+            if !crossings[0].1 {
+                crossings.rotate_left(1);
+            }
+
+            // Segments run from where the face leaves the negative region to
+            // where it enters it, keeping the negative region on their left
+            match crossings[..] {
+                [(enter, _), (exit, _)] => {
+                    next[exit] = enter as u8;
+                }
+                [(enter0, _), (exit0, _), (enter1, _), (exit1, _)] => {
+                    if connect_negative(f) {
+                        next[exit0] = enter1 as u8;
+                        next[exit1] = enter0 as u8;
+                    } else {
+                        next[exit0] = enter0 as u8;
+                        next[exit1] = enter1 as u8;
+                    }
+                }
+                _ => unreachable!("faces have at most four crossings"),
+            }
+        }
+
+        let mut visited = vec![false; self.edges.len()];
+        let mut loops = Vec::new();
+        for start in 0..self.edges.len() {
+            if next[start] == u8::MAX || visited[start] {
+                continue;
+            }
+
+            let mut edges = Vec::new();
+            let mut edge = start;
+            while !visited[edge] {
+                visited[edge] = true;
+                edges.push(edge as u8);
+                edge = next[edge] as usize;
+            }
+            loops.push(edges);
+        }
+        loops
+    }
+
+    /// Lookup table resolving every ambiguous face the same way
+    pub fn table(&self, connect_negative: bool) -> CellTable {
+        let triangles = (0..self.num_cases())
+            .map(|case| triangulate(&self.loops(case, |_| connect_negative)))
+            .collect();
+
+        CellTable { triangles }
+    }
+}
+
+/// Fan triangulates loops returned by [`CellShape::loops`], with triangles
+/// facing the positive samples
+pub(crate) fn triangulate(loops: &[Vec<u8>]) -> Vec<[u8; 3]> {
+    let mut triangles = Vec::new();
+    for edges in loops {
+        for i in 1..edges.len() - 1 {
+            triangles.push([edges[0], edges[i + 1], edges[i]]);
+        }
+    }
+    triangles
+}
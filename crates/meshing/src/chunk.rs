@@ -0,0 +1,315 @@
+use glam::Vec3;
+
+use crate::field::{gradient, ScalarField};
+use crate::marching_cubes::{MarchingCubesMode, NormalMode};
+use crate::mesh::Mesh;
+use crate::options::MeshingOptions;
+use crate::qef::QefOptions;
+use crate::{dual_contouring, marching_cubes, marching_cubes_33};
+
+/// Block of cells within a larger field
+///
+/// Meshing neighbouring chunks of the same field gives meshes that line up
+/// exactly along the shared faces. Positions are output in grid coordinates of
+/// the whole field, i.e. not normalized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chunk {
+    /// First cell of the chunk, in grid coordinates of the field
+    pub origin: [usize; 3],
+    /// Number of cells along x, y and z
+    pub size: [usize; 3],
+}
+
+impl Chunk {
+    pub fn new(origin: [usize; 3], size: [usize; 3]) -> Self {
+        Self { origin, size }
+    }
+
+    /// Converts a position relative to the chunk origin to field grid coordinates
+    pub fn to_world(&self, local: [f32; 3]) -> [f32; 3] {
+        (Vec3::from(local) + self.origin_f32()).into()
+    }
+
+    /// Converts a position in field grid coordinates to one relative to the chunk origin
+    pub fn to_local(&self, world: [f32; 3]) -> [f32; 3] {
+        (Vec3::from(world) - self.origin_f32()).into()
+    }
+
+    fn origin_f32(&self) -> Vec3 {
+        Vec3::new(
+            self.origin[0] as f32,
+            self.origin[1] as f32,
+            self.origin[2] as f32,
+        )
+    }
+
+    /// View of `field` covering the chunk plus `apron` samples past its far faces
+    fn view<'a, F: ScalarField + ?Sized>(&self, field: &'a F, apron: usize) -> ChunkField<'a, F> {
+        let dims = field.dims();
+        ChunkField {
+            field,
+            origin: self.origin,
+            dims: [0, 1, 2].map(|i| {
+                (self.size[i] + apron).min(dims[i].saturating_sub(self.origin[i]))
+            }),
+        }
+    }
+}
+
+/// Window into a field, sampling the neighbouring samples of the whole field
+/// when estimating gradients
+struct ChunkField<'a, F: ?Sized> {
+    field: &'a F,
+    origin: [usize; 3],
+    dims: [usize; 3],
+}
+
+impl<F: ScalarField + ?Sized> ScalarField for ChunkField<'_, F> {
+    fn dims(&self) -> [usize; 3] {
+        self.dims
+    }
+
+    fn sample(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.field.sample(self.origin[0] + x, self.origin[1] + y, self.origin[2] + z)
+    }
+
+    fn sample_relative(&self, x: usize, y: usize, z: usize, iso_level: f32) -> f32 {
+        self.field.sample_relative(self.origin[0] + x, self.origin[1] + y, self.origin[2] + z, iso_level)
+    }
+
+    fn gradient(&self, x: usize, y: usize, z: usize) -> Option<Vec3> {
+        Some(gradient(self.field, self.origin[0] + x, self.origin[1] + y, self.origin[2] + z))
+    }
+
+    fn sample_at(&self, p: Vec3) -> Option<f32> {
+        self.field.sample_at(p + self.origin_f32())
+    }
+
+    fn gradient_at(&self, p: Vec3) -> Option<Vec3> {
+        self.field.gradient_at(p + self.origin_f32())
+    }
+}
+
+impl<F: ?Sized> ChunkField<'_, F> {
+    fn origin_f32(&self) -> Vec3 {
+        Vec3::new(
+            self.origin[0] as f32,
+            self.origin[1] as f32,
+            self.origin[2] as f32,
+        )
+    }
+}
+
+/// Marching cubes over the cells of `chunk`
+///
+/// `field` must cover the chunk plus one sample past its far faces, which is
+/// shared with the neighbouring chunks. See [`crate::marching_cubes`].
+pub fn marching_cubes_chunk<F: ScalarField + ?Sized>(
+    field: &F,
+    chunk: &Chunk,
+    iso_level: f32,
+) -> Mesh {
+    marching_cubes::extract(&chunk.view(field, 1), iso_level, NormalMode::Face, chunk.origin, Vec3::ONE).into()
+}
+
+/// [`marching_cubes_chunk`] with the given options
+///
+/// Without a geometry set, positions stay in grid coordinates of the whole
+/// field rather than being normalized, so that the chunks line up.
+pub fn marching_cubes_chunk_with<F: ScalarField + ?Sized>(
+    field: &F,
+    chunk: &Chunk,
+    options: MeshingOptions,
+) -> Mesh {
+    let grid = options.geometry.unwrap_or_default();
+    let view = chunk.view(field, 1);
+    let (iso_level, normals, scale) = (options.iso_level, options.normals, grid.spacing);
+    let mesh = match options.mode {
+        MarchingCubesMode::Classic => marching_cubes::extract(&view, iso_level, normals, chunk.origin, scale),
+        MarchingCubesMode::Mc33 => marching_cubes_33::extract(&view, iso_level, normals, chunk.origin, scale),
+    };
+    options.finish(mesh.into(), &grid)
+}
+
+/// Indexed version of [`marching_cubes_chunk`]
+pub fn marching_cubes_chunk_indexed<F: ScalarField + ?Sized>(
+    field: &F,
+    chunk: &Chunk,
+    iso_level: f32,
+) -> Mesh {
+    marching_cubes::extract_indexed(&chunk.view(field, 1), iso_level, NormalMode::Face, chunk.origin, Vec3::ONE).into()
+}
+
+/// Indexed version of [`marching_cubes_chunk_with`]
+pub fn marching_cubes_chunk_indexed_with<F: ScalarField + ?Sized>(
+    field: &F,
+    chunk: &Chunk,
+    options: MeshingOptions,
+) -> Mesh {
+    let grid = options.geometry.unwrap_or_default();
+    let view = chunk.view(field, 1);
+    let (iso_level, normals, scale) = (options.iso_level, options.normals, grid.spacing);
+    let mesh = match options.mode {
+        MarchingCubesMode::Classic => marching_cubes::extract_indexed(&view, iso_level, normals, chunk.origin, scale),
+        MarchingCubesMode::Mc33 => {
+            marching_cubes_33::extract_indexed(&view, iso_level, normals, chunk.origin, scale)
+        }
+    };
+    options.finish(mesh.into(), &grid)
+}
+
+/// Dual contouring over the cells of `chunk`
+///
+/// Quads are generated for the grid edges owned by the chunk, which requires
+/// the vertices of the first layer of cells in the neighbouring chunks. `field`
+/// must therefore cover the chunk plus two samples past its far faces. See
+/// [`crate::dual_contouring`].
+pub fn dual_contouring_chunk<F: ScalarField + ?Sized>(
+    field: &F,
+    chunk: &Chunk,
+    iso_level: f32,
+) -> Mesh {
+    let options = QefOptions::default();
+    dual_contouring::extract(&chunk.view(field, 2), iso_level, &options, chunk.size, chunk.origin, Vec3::ONE)
+}
+
+/// [`dual_contouring_chunk`] with the given options
+///
+/// Without a geometry set, positions stay in grid coordinates of the whole
+/// field, as for [`marching_cubes_chunk_with`].
+pub fn dual_contouring_chunk_with<F: ScalarField + ?Sized>(
+    field: &F,
+    chunk: &Chunk,
+    options: MeshingOptions,
+) -> Mesh {
+    let grid = options.geometry.unwrap_or_default();
+    let view = chunk.view(field, 2);
+    let mesh = dual_contouring::extract(&view, options.iso_level, &options.qef, chunk.size, chunk.origin, grid.spacing);
+    options.finish(mesh, &grid)
+}
+
+/// Indexed version of [`dual_contouring_chunk`]
+pub fn dual_contouring_chunk_indexed<F: ScalarField + ?Sized>(
+    field: &F,
+    chunk: &Chunk,
+    iso_level: f32,
+    quads: bool,
+) -> Mesh {
+    dual_contouring_indexed(field, chunk, iso_level, quads, &QefOptions::default(), Vec3::ONE)
+}
+
+/// Indexed version of [`dual_contouring_chunk_with`]
+pub fn dual_contouring_chunk_indexed_with<F: ScalarField + ?Sized>(
+    field: &F,
+    chunk: &Chunk,
+    quads: bool,
+    options: MeshingOptions,
+) -> Mesh {
+    let grid = options.geometry.unwrap_or_default();
+    let mesh = dual_contouring_indexed(field, chunk, options.iso_level, quads, &options.qef, grid.spacing);
+    options.finish(mesh, &grid)
+}
+
+/// Indexed dual contouring over the cells of `chunk`, with vertices scaled
+/// by `scale`
+///
+/// The vertices of the apron cells that no quad of the chunk uses are
+/// removed.
+fn dual_contouring_indexed<F: ScalarField + ?Sized>(
+    field: &F,
+    chunk: &Chunk,
+    iso_level: f32,
+    quads: bool,
+    options: &QefOptions,
+    scale: Vec3,
+) -> Mesh {
+    let view = chunk.view(field, 2);
+    let mut mesh = dual_contouring::extract_indexed(&view, iso_level, quads, options, chunk.size, chunk.origin, scale);
+    mesh.remove_unused_vertices();
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+    use crate::geometry::GridGeometry;
+    use crate::test_util::{boundary_edges, soup, sphere};
+
+    #[test]
+    fn default_options_match_plain_chunks() {
+        let field = sphere([17; 3], Vec3::splat(8.0), 5.3);
+        let chunk = Chunk::new([8, 0, 0], [8, 16, 16]);
+        let options = MeshingOptions::new();
+
+        assert_eq!(marching_cubes_chunk_with(&field, &chunk, options), marching_cubes_chunk(&field, &chunk, 0.0));
+        assert_eq!(
+            dual_contouring_chunk_indexed_with(&field, &chunk, true, options),
+            dual_contouring_chunk_indexed(&field, &chunk, 0.0, true),
+        );
+    }
+
+    #[test]
+    fn options_are_applied_to_chunks() {
+        let field = sphere([17; 3], Vec3::splat(8.0), 5.3);
+        let chunk = Chunk::new([8, 0, 0], [8, 16, 16]);
+        let plain = dual_contouring_chunk(&field, &chunk, 0.0);
+
+        let spacing = Vec3::new(0.5, 1.0, 2.0);
+        let options = MeshingOptions::new().geometry(GridGeometry::new(Vec3::ZERO, spacing));
+        let scaled = dual_contouring_chunk_with(&field, &chunk, options);
+        assert_eq!(scaled.positions.len(), plain.positions.len());
+        for (&p, &q) in plain.positions.iter().zip(&scaled.positions) {
+            assert!((Vec3::from(p) * spacing - Vec3::from(q)).length() < 1e-5);
+        }
+
+        let unbiased = dual_contouring_chunk_with(&field, &chunk, MeshingOptions::new().bias_strength(0.0));
+        assert_ne!(unbiased.positions, plain.positions);
+    }
+
+    /// Meshes of the 3 x 3 x 3 chunks of 10 cells covering a sphere
+    fn chunked(mesh_chunk: impl Fn(&dyn ScalarField, &Chunk) -> Mesh) -> Mesh {
+        let field = sphere([31; 3], Vec3::splat(15.0), 11.3);
+        let mut merged = Mesh::default();
+        for z in 0..3 {
+            for y in 0..3 {
+                for x in 0..3 {
+                    let chunk = Chunk::new([10 * x, 10 * y, 10 * z], [10; 3]);
+                    merged.merge(&mesh_chunk(&field, &chunk));
+                }
+            }
+        }
+        merged
+    }
+
+    #[test]
+    fn chunks_line_up() {
+        for mesh in [
+            chunked(|field, chunk| marching_cubes_chunk(field, chunk, 0.0)),
+            chunked(|field, chunk| marching_cubes_chunk_indexed(field, chunk, 0.0)),
+            chunked(|field, chunk| {
+                marching_cubes_chunk_with(field, chunk, MeshingOptions::new().mode(MarchingCubesMode::Mc33))
+            }),
+            chunked(|field, chunk| dual_contouring_chunk(field, chunk, 0.0)),
+            chunked(|field, chunk| dual_contouring_chunk_indexed(field, chunk, 0.0, false)),
+        ] {
+            assert!(!mesh.is_empty());
+            assert_eq!(boundary_edges(&soup(&mesh)), 0);
+        }
+    }
+
+    #[test]
+    fn indexed_dual_contouring_chunks_use_every_vertex() {
+        let mesh = chunked(|field, chunk| {
+            let mesh = dual_contouring_chunk_indexed(field, chunk, 0.0, true);
+            let mut used = vec![false; mesh.num_vertices()];
+            for &i in mesh.indices.as_ref().unwrap() {
+                used[i as usize] = true;
+            }
+            assert!(used.iter().all(|&u| u), "{chunk:?}");
+            mesh
+        });
+        assert!(!mesh.is_empty());
+    }
+}
@@ -0,0 +1,214 @@
+use glam::Vec3;
+
+/// Normal of the triangles with zero area, and of the vertices with only
+/// such triangles around them
+const FALLBACK_NORMAL: Vec3 = Vec3::Z;
+
+/// `normal` scaled to unit length, or [`FALLBACK_NORMAL`] if it is zero
+///
+/// Keeps the output normals valid for lighting even where the surface has no
+/// direction.
+pub(crate) fn unit_normal(normal: Vec3) -> Vec3 {
+    normal.try_normalize().unwrap_or(FALLBACK_NORMAL)
+}
+
+/// Unit normal of the counter-clockwise triangle, see [`unit_normal`]
+pub(crate) fn face_normal(v0: Vec3, v1: Vec3, v2: Vec3) -> Vec3 {
+    unit_normal((v1 - v0).cross(v2 - v0))
+}
+
+/// Whether the triangle has no area, and therefore no normal
+pub(crate) fn degenerate(v0: Vec3, v1: Vec3, v2: Vec3) -> bool {
+    (v1 - v0).cross(v2 - v0).try_normalize().is_none()
+}
+
+/// Removes the triangles with zero area from a triangle soup
+///
+/// Such triangles come from crossings landing on grid points, where the
+/// crossings on several edges coincide, or from dual contouring vertices
+/// clamped to the same point. They cover no pixels, but have no normal either
+/// and the extractors give them an arbitrary unit one. Returns the number of
+/// triangles removed.
+pub fn remove_degenerate_triangles(
+    positions: &mut Vec<[f32;3]>,
+    normals: &mut Vec<[f32;3]>,
+) -> usize {
+    let num_triangles = positions.len() / 3;
+    remove_soup(positions, normals);
+    num_triangles - positions.len() / 3
+}
+
+/// [`remove_degenerate_triangles`], returning the index each remaining vertex
+/// had before
+pub(crate) fn remove_soup(positions: &mut Vec<[f32;3]>, normals: &mut Vec<[f32;3]>) -> Vec<u32> {
+    let mut sources = Vec::with_capacity(positions.len());
+    for t in 0..positions.len() / 3 {
+        let [v0, v1, v2] = [0, 1, 2].map(|i| Vec3::from(positions[3 * t + i]));
+        if degenerate(v0, v1, v2) {
+            continue;
+        }
+
+        for i in 0..3 {
+            let kept = sources.len();
+            positions[kept] = positions[3 * t + i];
+            normals[kept] = normals[3 * t + i];
+            sources.push((3 * t + i) as u32);
+        }
+    }
+
+    positions.truncate(sources.len());
+    normals.truncate(sources.len());
+    sources
+}
+
+/// Removes the triangles with zero area from an indexed triangle mesh, see
+/// [`remove_degenerate_triangles`]
+///
+/// Vertices at the same position within a triangle are welded first, so that
+/// the triangles around a removed one stay connected. The normals of welded
+/// vertices are averaged and vertices no longer used by any triangle are
+/// removed. Returns the number of triangles removed.
+pub fn remove_degenerate_indexed(
+    positions: &mut Vec<[f32;3]>,
+    normals: &mut Vec<[f32;3]>,
+    indices: &mut Vec<u32>,
+) -> usize {
+    let num_triangles = indices.len() / 3;
+    remove_indexed(positions, normals, indices);
+    num_triangles - indices.len() / 3
+}
+
+/// [`remove_degenerate_indexed`], returning the index each remaining vertex
+/// had before
+pub(crate) fn remove_indexed(
+    positions: &mut Vec<[f32;3]>,
+    normals: &mut Vec<[f32;3]>,
+    indices: &mut Vec<u32>,
+) -> Vec<u32> {
+    // Vertices are welded into the one with the lowest index
+    let mut parent: Vec<u32> = (0..positions.len() as u32).collect();
+    fn find(parent: &mut [u32], mut v: u32) -> u32 {
+        while parent[v as usize] != v {
+            parent[v as usize] = parent[parent[v as usize] as usize];
+            v = parent[v as usize];
+        }
+        v
+    }
+
+    for triangle in indices.chunks_exact(3) {
+        for k in 0..3 {
+            let (a, b) = (triangle[k], triangle[(k + 1) % 3]);
+            if positions[a as usize] == positions[b as usize] {
+                let (a, b) = (find(&mut parent, a), find(&mut parent, b));
+                parent[a.max(b) as usize] = a.min(b);
+            }
+        }
+    }
+
+    let mut kept = Vec::with_capacity(indices.len());
+    for triangle in indices.chunks_exact(3) {
+        let [i0, i1, i2] = [0, 1, 2].map(|k| find(&mut parent, triangle[k]));
+        let [v0, v1, v2] = [i0, i1, i2].map(|i| Vec3::from(positions[i as usize]));
+        if i0 == i1 || i1 == i2 || i2 == i0 || degenerate(v0, v1, v2) {
+            continue;
+        }
+        kept.extend_from_slice(&[i0, i1, i2]);
+    }
+
+    let mut normal_sums = vec![Vec3::ZERO; positions.len()];
+    for v in 0..positions.len() as u32 {
+        normal_sums[find(&mut parent, v) as usize] += Vec3::from(normals[v as usize]);
+    }
+
+    // Compact the vertices still in use, keeping their order
+    let mut used = vec![false; positions.len()];
+    for &i in &kept {
+        used[i as usize] = true;
+    }
+    let mut new_index = vec![0_u32; positions.len()];
+    let mut sources = Vec::new();
+    for v in 0..positions.len() {
+        if used[v] {
+            let num_vertices = sources.len();
+            new_index[v] = num_vertices as u32;
+            positions[num_vertices] = positions[v];
+            normals[num_vertices] = unit_normal(normal_sums[v]).into();
+            sources.push(v as u32);
+        }
+    }
+    positions.truncate(sources.len());
+    normals.truncate(sources.len());
+
+    *indices = kept.into_iter().map(|i| new_index[i as usize]).collect();
+    sources
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use crate::test_util::{cuboid, FnField};
+    use crate::{dual_contouring_indexed_with, marching_cubes_indexed_with, marching_cubes_into, marching_cubes_with};
+    use crate::{Mesh, MeshingOptions, MeshingScratch};
+
+    /// Sphere with integer densities, so that many crossings land on grid
+    /// points and give triangles with zero area
+    fn rounded_sphere() -> FnField<impl Fn([usize; 3]) -> f32 + Sync> {
+        FnField {
+            dims: [12, 12, 12],
+            f: |[x, y, z]: [usize; 3]| (Vec3::new(x as f32, y as f32, z as f32) - 5.5).length().round() - 4.0,
+        }
+    }
+
+    fn assert_unit_normals(mesh: &Mesh) {
+        for &n in &mesh.normals {
+            assert!((Vec3::from(n).length() - 1.0).abs() < 1e-5, "{n:?}");
+        }
+    }
+
+    #[test]
+    fn degenerate_triangles_get_unit_normals() {
+        let field = rounded_sphere();
+        for mesh in [marching_cubes_with(&field, MeshingOptions::new()), marching_cubes_indexed_with(&field, MeshingOptions::new())] {
+            assert_eq!(mesh.removed_degenerate, 0);
+            assert!(mesh.triangles().any(|[v0, v1, v2]| super::degenerate(v0, v1, v2)));
+            assert_unit_normals(&mesh);
+        }
+    }
+
+    #[test]
+    fn removed_triangles_are_counted() {
+        let field = rounded_sphere();
+        let options = MeshingOptions::new().remove_degenerate(true);
+        for (kept, all) in [
+            (marching_cubes_with(&field, options), marching_cubes_with(&field, MeshingOptions::new())),
+            (marching_cubes_indexed_with(&field, options), marching_cubes_indexed_with(&field, MeshingOptions::new())),
+        ] {
+            assert!(kept.removed_degenerate > 0);
+            assert_eq!(kept.removed_degenerate, all.num_triangles() - kept.num_triangles());
+            assert!(!kept.triangles().any(|[v0, v1, v2]| super::degenerate(v0, v1, v2)));
+        }
+
+        let mut streamed = Mesh::default();
+        marching_cubes_into(&field, options, &mut MeshingScratch::new(), &mut streamed);
+        let all = marching_cubes_indexed_with(&field, MeshingOptions::new());
+        assert_eq!(streamed.removed_degenerate, all.num_triangles() - streamed.num_triangles());
+        assert_unit_normals(&streamed);
+    }
+
+    #[test]
+    fn degenerate_quads_are_removed() {
+        // Without the bias, vertices past the corners are clamped onto shared
+        // corners of their cells
+        let field = cuboid([12; 3], Vec3::splat(5.8), Vec3::splat(3.2));
+        let options = MeshingOptions::new().bias_strength(0.0);
+        let all = dual_contouring_indexed_with(&field, true, options);
+        let num_degenerate = all.triangles().filter(|&[v0, v1, v2]| super::degenerate(v0, v1, v2)).count();
+        assert!(num_degenerate > 0);
+
+        let kept = dual_contouring_indexed_with(&field, true, options.remove_degenerate(true));
+        assert_eq!(kept.removed_degenerate, num_degenerate);
+        assert_eq!(kept.num_triangles(), all.num_triangles() - num_degenerate);
+        assert!(!kept.triangles().any(|[v0, v1, v2]| super::degenerate(v0, v1, v2)));
+    }
+}
@@ -1,92 +1,190 @@
-use glam::{Vec3, Vec4};
-
-fn determinant(m: &[[f32; 3]; 3]) -> f32 {
-    m[0][0] * m[1][1] * m[2][2] + m[0][1] * m[1][2] * m[2][0] + m[0][2] * m[1][0] * m[2][1]
-        - m[0][2] * m[1][1] * m[2][0] - m[0][1] * m[1][0] * m[2][2] - m[0][0] * m[1][2] * m[2][1]
-}
-
-fn solve3x3(m: &[[f32; 3]; 3], b: &[f32; 3]) -> Option<[f32; 3]> {
-    let det = determinant(m);
-    if det.abs() <= std::f32::EPSILON {
+use std::ops::Range;
+
+use glam::Vec3;
+
+use crate::degenerate::{face_normal, unit_normal};
+use crate::error::MeshingError;
+use crate::field::{edge_root, gradient, index, normalize_scale, validate_field, ScalarField};
+use crate::mesh::Mesh;
+use crate::options::MeshingOptions;
+use crate::parallel::map_slabs;
+use crate::qef::{Qef, QefOptions};
+
+pub(crate) const CORNERS: [(usize, usize, usize); 8] = [
+    (0, 0, 0),
+    (0, 0, 1),
+    (0, 1, 0),
+    (0, 1, 1),
+    (1, 0, 0),
+    (1, 0, 1),
+    (1, 1, 0),
+    (1, 1, 1),
+];
+
+/// Crossing on the edge between corners `c0` and `c1` of cell (x, y, z) and
+/// the surface normal there, if the surface crosses the edge
+///
+/// The crossing is found with [`edge_root`] and its normal is taken from
+/// [`ScalarField::gradient_at`], or interpolated between the gradients at
+/// both corners. Positions are relative to the minimum corner of the cell.
+/// `c0` must be the lower corner.
+pub(crate) fn edge_crossing<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    [x, y, z]: [usize; 3],
+    c0: (usize, usize, usize),
+    c1: (usize, usize, usize),
+) -> Option<(Vec3, Vec3)> {
+    let v0 = field.sample_relative(x + c0.0, y + c0.1, z + c0.2, iso_level);
+    let v1 = field.sample_relative(x + c1.0, y + c1.1, z + c1.2, iso_level);
+
+    if (v0 > 0.0) == (v1 > 0.0) {
         return None;
     }
 
-    let mut x = [0.0_f32; 3];
+    let cell = Vec3::new(x as f32, y as f32, z as f32);
+    let p0 = Vec3::new(c0.0 as f32, c0.1 as f32, c0.2 as f32);
+    let p1 = Vec3::new(c1.0 as f32, c1.1 as f32, c1.2 as f32);
+    let t = edge_root(field, iso_level, (cell + p0, v0), (cell + p1, v1));
+    let p = p0 + t * (p1 - p0);
+
+    let n = field.gradient_at(cell + p).unwrap_or_else(|| {
+        let g0 = gradient(field, x + c0.0, y + c0.1, z + c0.2);
+        let g1 = gradient(field, x + c1.0, y + c1.1, z + c1.2);
+        g0.lerp(g1, t)
+    });
+
+    Some((p, n.normalize_or_zero()))
+}
+
+/// QEF of the tangent planes at the crossings on the edges of cell (x, y, z),
+/// see [`edge_crossing`]
+///
+/// Each crossing is moved by `offset` before being added.
+pub(crate) fn cell_qef<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    cell: [usize; 3],
+    offset: Vec3,
+) -> Qef {
+    let mut qef = Qef::new();
+    let mut push = |crossing: Option<(Vec3, Vec3)>| {
+        if let Some((p, n)) = crossing {
+            qef.add(offset + p, n);
+        }
+    };
 
-    #[allow(clippy::needless_range_loop)]
-    for i in 0..3 {
-        let mut m2 = *m;
-        for j in 0..3 {
-            m2[j][i] = b[j];
+    for dy in 0..2 {
+        for dx in 0..2 {
+            push(edge_crossing(field, iso_level, cell, (dx, dy, 0), (dx, dy, 1)));
         }
-        x[i] = determinant(&m2) / det;
     }
-    Some(x)
-}
-/// https://www.mattkeeter.com/projects/qef/
-#[allow(non_snake_case)]
-fn qef_solve(candidates: &[Vec4]) -> Option<[f32; 3]> {
-    let mut At_A = [[0.0_f32; 3];3];
-    let mut At_b = [0.0_f32; 3];
-
-    for i in 0..3 {
-        for j in 0..3 {
-            let mut sum = 0.0_f32;
-            for c in candidates {
-                sum += c[i] * c[j];
-            }
-            At_A[i][j] = sum;
+
+    for dz in 0..2 {
+        for dx in 0..2 {
+            push(edge_crossing(field, iso_level, cell, (dx, 0, dz), (dx, 1, dz)));
         }
     }
 
-    for i in 0..3 {
-        let mut sum = 0.0_f32;
-        for c in candidates {
-            sum += c[i] * c[3];
+    for dz in 0..2 {
+        for dy in 0..2 {
+            push(edge_crossing(field, iso_level, cell, (0, dy, dz), (1, dy, dz)));
         }
-        At_b[i] = sum;
     }
 
-    solve3x3(&At_A, &At_b)
+    qef
 }
 
-fn index(x: usize, y: usize, z: usize, width: usize, height: usize) -> usize {
-    x + y * width + z * width * height
+/// Marks cells without a vertex in the cell -> vertex map
+pub(crate) const NO_VERTEX: u32 = u32::MAX;
+
+/// Places the vertex of cell (x, y, z) at the minimizer of its QEF, see
+/// [`cell_qef`]
+pub(crate) fn qef_vertex<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    cell: [usize; 3],
+    options: &QefOptions,
+) -> Option<Vec3> {
+    let qef = cell_qef(field, iso_level, cell, Vec3::ZERO);
+    if qef.is_empty() {
+        return None;
+    }
+
+    let (vertex, _) = qef.solve_in_cell(Vec3::ZERO, Vec3::ONE, options);
+    Some(vertex)
 }
 
-/// Implements J Tao, et al., Dual Contouring of Hermite Data
-pub fn dual_contouring(
-    density: &[f32],
-    normal: &[Vec3],
-    width: usize,
-    height: usize,
-    depth: usize
-) -> (Vec<[f32;3]>, Vec<[f32;3]>) {
-    let corners = [
-        (0, 0, 0),
-        (0, 0, 1),
-        (0, 1, 0),
-        (0, 1, 1),
-        (1, 0, 0),
-        (1, 0, 1),
-        (1, 1, 0),
-        (1, 1, 1),
-    ];
-    let far_edges = [
-        (3, 7),
-        (5, 7),
-        (6, 7)
-    ];
+/// Computes one vertex per cell containing the surface
+///
+/// `place` returns the position of the vertex relative to the minimum corner
+/// of the cell, or `None` to leave the cell without one. Vertices are placed
+/// at `(origin + p) * scale`, where `p` is the position in grid coordinates
+/// of `field`. Returns the vertex positions and a map from cell index to
+/// vertex index.
+pub(crate) fn cell_vertices<F, P>(
+    field: &F,
+    iso_level: f32,
+    origin: [usize; 3],
+    scale: Vec3,
+    place: P,
+) -> (Vec<Vec3>, Vec<u32>)
+where
+    F: ScalarField + ?Sized,
+    P: Fn([usize; 3]) -> Option<Vec3> + Sync,
+{
+    let [width, height, depth] = field.dims();
+
+    let slabs = map_slabs(depth - 1, |layers| {
+        let mut vertices = Vec::<Vec3>::new();
+        let mut cell_to_vertex = vec![NO_VERTEX; width * height * layers.len()];
+        layer_vertices(field, iso_level, origin, scale, &place, layers, &mut vertices, &mut cell_to_vertex);
+        (vertices, cell_to_vertex)
+    });
+
+    let mut vertices = Vec::<Vec3>::new();
+    let mut cell_to_vertex = Vec::<u32>::with_capacity(width * height * depth);
+    for (slab_vertices, slab_cell_to_vertex) in slabs {
+        let offset = vertices.len() as u32;
+        vertices.extend(slab_vertices);
+        cell_to_vertex.extend(slab_cell_to_vertex.into_iter().map(|v| {
+            if v == NO_VERTEX { v } else { v + offset }
+        }));
+    }
+    // The last layer of samples has no cells
+    cell_to_vertex.resize(width * height * depth, NO_VERTEX);
 
-    let mut vertices = vec![Vec3::ZERO; width * height * depth];
-    let mut candidates = Vec::<Vec4>::new();
+    (vertices, cell_to_vertex)
+}
 
-    for z in 0..depth-1 {
+/// Appends the vertices of the cells in `layers` along z to `vertices`, see
+/// [`cell_vertices`]
+///
+/// `cell_to_vertex` maps the cells of `layers` only, the first layer
+/// starting at index 0.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn layer_vertices<F, P>(
+    field: &F,
+    iso_level: f32,
+    origin: [usize; 3],
+    scale: Vec3,
+    place: &P,
+    layers: Range<usize>,
+    vertices: &mut Vec<Vec3>,
+    cell_to_vertex: &mut [u32],
+)
+where
+    F: ScalarField + ?Sized,
+    P: Fn([usize; 3]) -> Option<Vec3>,
+{
+    let [width, height, _] = field.dims();
+
+    for z in layers.clone() {
         for y in 0..height-1 {
             for x in 0..width-1 {
                 let mut num_inside = 0;
-                for i in 0..8 {
-                    if density[index(x + corners[i].0, y + corners[i].1, z + corners[i].2, width, height)] <= 0.0 {
+                for corner in CORNERS {
+                    if field.sample_relative(x + corner.0, y + corner.1, z + corner.2, iso_level) <= 0.0 {
                         num_inside += 1;
                     }
                 }
@@ -95,173 +193,399 @@ pub fn dual_contouring(
                     continue;
                 }
 
-                let mut mass_point = Vec3::new(0.0, 0.0, 0.0);
-                candidates.clear();
-
-                for dy in 0..2 {
-                    for dx in 0..2 {
-                        let v0 = density[index(x + dx, y + dy, z, width, height)];
-                        let v1 = density[index(x + dx, y + dy, z + 1, width, height)];
+                let Some(vertex) = place([x, y, z]) else {
+                    continue;
+                };
 
-                        if (v0 > 0.0) != (v1 > 0.0) {
-                            let t = v0 / (v0 - v1);
-                            let p = Vec3::new(dx as f32, dy as f32, t);
-                            let n = normal[index(x + dx, y + dy, z, width, height)];
+                cell_to_vertex[index(x, y, z - layers.start, width, height)] = vertices.len() as u32;
+                vertices.push(Vec3::new(
+                    (origin[0] + x) as f32 + vertex[0],
+                    (origin[1] + y) as f32 + vertex[1],
+                    (origin[2] + z) as f32 + vertex[2],
+                ) * scale);
+            }
+        }
+    }
+}
 
-                            candidates.push(Vec4::new(n.x, n.y, n.z, p.dot(n)));
-                            mass_point += p;
-                        }
-                    }
-                }
+/// [`cell_quads`] for meshes with one vertex per cell
+pub(crate) fn cell_vertex_quads<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    cells: [usize; 3],
+    cell_to_vertex: &[u32],
+) -> Vec<[u32; 4]> {
+    let [width, height, _] = field.dims();
+    cell_quads(field, iso_level, cells, |[x, y, z], _| {
+        cell_to_vertex[index(x, y, z, width, height)]
+    })
+}
 
-                for dz in 0..2 {
-                    for dx in 0..2 {
-                        let v0 = density[index(x + dx, y, z + dz, width, height)];
-                        let v1 = density[index(x + dx, y + 1, z + dz, width, height)];
+/// Limit passed to [`cell_quads`] to generate the quads of the whole field
+pub(crate) const ALL_CELLS: [usize; 3] = [usize::MAX; 3];
+
+/// Vertex indices of every quad, in counter-clockwise order
+///
+/// `vertex` returns the vertex of cell (x, y, z) for the crossing on the edge
+/// between the given pair of its corners. Quads are generated for the edges
+/// owned by a chunk of `cells` cells, which start in its first `cells` layers
+/// of grid points along their axis and lie past its first layer across it.
+/// Edges on the boundary of `field` have fewer than four cells around them
+/// and get no quad.
+pub(crate) fn cell_quads<F, V>(
+    field: &F,
+    iso_level: f32,
+    cells: [usize; 3],
+    vertex: V,
+) -> Vec<[u32; 4]>
+where
+    F: ScalarField + ?Sized,
+    V: Fn([usize; 3], (usize, usize)) -> u32 + Sync,
+{
+    let num_cells = quad_cells(field.dims(), cells);
+
+    let slabs = map_slabs(num_cells[2], |layers| {
+        let mut quads = Vec::<[u32; 4]>::new();
+        layer_quads(field, iso_level, cells, &vertex, layers, &mut quads);
+        quads
+    });
+    slabs.concat()
+}
 
-                        if (v0 > 0.0) != (v1 > 0.0) {
-                            let t = v0 / (v0 - v1);
-                            let p = Vec3::new(dx as f32, t, dz as f32);
-                            let n = normal[index(x + dx, y, z + dz, width, height)];
+/// Cells [`cell_quads`] visits along each axis
+pub(crate) fn quad_cells(dims: [usize; 3], cells: [usize; 3]) -> [usize; 3] {
+    [0, 1, 2].map(|i| cells[i].min(dims[i] - 1))
+}
 
-                            candidates.push(Vec4::new(n.x, n.y, n.z, p.dot(n)));
-                            mass_point += p;
-                        }
-                    }
-                }
+/// Appends the quads of the cells in `layers` along z to `quads`, see
+/// [`cell_quads`]
+pub(crate) fn layer_quads<F, V>(
+    field: &F,
+    iso_level: f32,
+    cells: [usize; 3],
+    vertex: &V,
+    layers: Range<usize>,
+    quads: &mut Vec<[u32; 4]>,
+)
+where
+    F: ScalarField + ?Sized,
+    V: Fn([usize; 3], (usize, usize)) -> u32,
+{
+    let dims = field.dims();
+    let num_cells = quad_cells(dims, cells);
+    // Last grid point an edge across each axis may lie on
+    let last_point = [0, 1, 2].map(|i| cells[i].min(dims[i] - 2));
 
-                for dz in 0..2 {
-                    for dy in 0..2 {
-                        let v0 = density[index(x, y + dy, z + dz, width, height)];
-                        let v1 = density[index(x + 1, y + dy, z + dz, width, height)];
+    let far_edges = [
+        (3, 7),
+        (5, 7),
+        (6, 7)
+    ];
 
-                        if (v0 > 0.0) != (v1 > 0.0) {
-                            let t = v0 / (v0 - v1);
-                            let p = Vec3::new(t, dy as f32, dz as f32);
-                            let n = normal[index(x, y + dy, z + dz, width, height)];
+    // Offsets of the cells sharing each far edge
+    let neighbours = [
+        [(0, 0, 0), (0, 0, 1), (0, 1, 0), (0, 1, 1)],
+        [(0, 0, 0), (0, 0, 1), (1, 0, 0), (1, 0, 1)],
+        [(0, 0, 0), (0, 1, 0), (1, 0, 0), (1, 1, 0)],
+    ];
 
-                            candidates.push(Vec4::new(n.x, n.y, n.z, p.dot(n)));
-                            mass_point += p;
-                        }
-                    }
+    for z in layers {
+        for y in 0..num_cells[1] {
+            for x in 0..num_cells[0] {
+                let mut inside = [false; 8];
+                for i in 0..8 {
+                    inside[i] = field.sample_relative(x + CORNERS[i].0, y + CORNERS[i].1, z + CORNERS[i].2, iso_level) <= 0.0;
                 }
 
-                let num_candidates = candidates.len();
-                if num_candidates == 0 {
-                    continue;
-                }
+                #[allow(clippy::needless_range_loop)]
+                for face in 0..3 {
+                    // The far edge of face `face` runs along that axis
+                    let e = far_edges[face];
+                    let cell = [x, y, z];
+                    if inside[e.0] == inside[e.1]
+                        || (0..3).any(|i| i != face && cell[i] + 1 > last_point[i])
+                    {
+                        continue;
+                    }
 
-                mass_point /= num_candidates as f32;
-
-                let bias_strength = 1.0;
-                let n = Vec3::new(bias_strength, 0.0, 0.0);
-                candidates.push(Vec4::new(n.x, n.y, n.z, mass_point.dot(n)));
-                let n = Vec3::new(0.0, bias_strength, 0.0);
-                candidates.push(Vec4::new(n.x, n.y, n.z, mass_point.dot(n)));
-                let n = Vec3::new(0.0, 0.0, bias_strength);
-                candidates.push(Vec4::new(n.x, n.y, n.z, mass_point.dot(n)));
-                
-                let vertex = if let Some(vertex) = qef_solve(&candidates) {[
-                    vertex[0].min(1.0).max(0.0),
-                    vertex[1].min(1.0).max(0.0),
-                    vertex[2].min(1.0).max(0.0),
-                ]} else {
-                    // If the QEF solver fails, use the center
-                    [0.5, 0.5, 0.5]
-                };
+                    let [v0, v1, v2, v3] = neighbours[face].map(|o| {
+                        // The same edge, seen from the neighbouring cell
+                        let shift = 4 * o.0 + 2 * o.1 + o.2;
+                        vertex([x + o.0, y + o.1, z + o.2], (e.0 - shift, e.1 - shift))
+                    });
 
-                vertices[index(x, y, z, width, height)] = Vec3::new(
-                    (x as f32 + vertex[0]) / width as f32,
-                    (y as f32 + vertex[1]) / height as f32,
-                    (z as f32 + vertex[2]) / depth as f32,
-                );
+                    if inside[e.0] == (face == 1) {
+                        quads.push([v0, v1, v3, v2]);
+                    }
+                    else {
+                        quads.push([v0, v2, v3, v1]);
+                    }
+                }
             }
         }
     }
+}
+
+/// Whether [`cell_quads`] generates a quad for the edge from grid point `p`
+/// along `axis` when meshing the whole field, i.e. whether the edge has four
+/// cells around it
+pub(crate) fn has_quad(dims: [usize; 3], p: [usize; 3], axis: usize) -> bool {
+    (0..3).all(|i| p[i] + 2 <= dims[i] && (i == axis || p[i] >= 1))
+}
+
+/// Implements J Tao, et al., Dual Contouring of Hermite Data
+///
+/// Extracts the isosurface where the field equals `iso_level`. Samples with a
+/// density at or below `iso_level` are considered inside. Intersection normals
+/// are interpolated between [`ScalarField::gradient`] at both ends of the
+/// edge, estimated with central differences if the field has none. Fields that
+/// can be evaluated between grid points get their crossings located by root
+/// finding and normals from [`ScalarField::gradient_at`] instead.
+pub fn dual_contouring<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+) -> Mesh {
+    extract(field, iso_level, &QefOptions::default(), ALL_CELLS, [0, 0, 0], normalize_scale(field))
+}
 
+/// [`dual_contouring`], failing rather than panicking or meshing NaNs if
+/// `field` is invalid, see [`validate_field`]
+pub fn try_dual_contouring<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+) -> Result<Mesh, MeshingError> {
+    validate_field(field)?;
+    Ok(dual_contouring(field, iso_level))
+}
+
+/// [`dual_contouring`] with the given options, including the placement of the
+/// vertices in their cells
+pub fn dual_contouring_with<F: ScalarField + ?Sized>(
+    field: &F,
+    options: MeshingOptions,
+) -> Mesh {
+    let grid = options.grid(field);
+    let mesh = extract(field, options.iso_level, &options.qef, ALL_CELLS, [0, 0, 0], grid.spacing);
+    options.finish(mesh, &grid)
+}
+
+/// Dual contouring with vertices at `(origin + p) * scale`, see [`cell_vertices`],
+/// and the quads of a chunk of `cells` cells, see [`cell_quads`]
+pub(crate) fn extract<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    options: &QefOptions,
+    cells: [usize; 3],
+    origin: [usize; 3],
+    scale: Vec3,
+) -> Mesh {
+    let (vertices, cell_to_vertex) = cell_vertices(field, iso_level, origin, scale, |cell| {
+        qef_vertex(field, iso_level, cell, options)
+    });
+
+    soup_mesh(&vertices, &cell_vertex_quads(field, iso_level, cells, &cell_to_vertex))
+}
+
+/// Splits `cell_quads` into triangles with flat normals
+pub(crate) fn soup_mesh(
+    vertices: &[Vec3],
+    cell_quads: &[[u32; 4]],
+) -> Mesh {
     let mut mesh_positions = Vec::<[f32;3]>::new();
     let mut mesh_normals = Vec::<[f32;3]>::new();
 
-    for z in 0..depth-2 {
-        for y in 0..height-2 {
-            for x in 0..width-2 {
-                let mut inside = [false; 8];
-                for i in 0..8 {
-                    inside[i] = density[index(x + corners[i].0, y + corners[i].1, z + corners[i].2, width, height)] <= 0.0;
-                }
+    for quad in cell_quads {
+        let [v0, v1, v2, v3] = quad.map(|v| vertices[v as usize]);
 
-                #[allow(clippy::needless_range_loop)]
-                for face in 0..3 {
-                    let e = far_edges[face];
-                    if inside[e.0] == inside[e.1] {
-                        continue;
-                    }
+        mesh_positions.push(v0.into());
+        mesh_positions.push(v1.into());
+        mesh_positions.push(v2.into());
 
-                    let v0 = vertices[index(x, y, z, width, height)];
-                    let (v1, v2, v3) = match face {
-                        0 => (
-                            vertices[index(x, y,   z+1, width, height)],
-                            vertices[index(x, y+1, z, width, height)],
-                            vertices[index(x, y+1, z+1, width, height)],
-                        ),
-                        1 => (
-                            vertices[index(x, y,   z+1, width, height)],
-                            vertices[index(x+1, y, z, width, height)],
-                            vertices[index(x+1, y, z+1, width, height)],
-                        ),
-                        2 => (
-                            vertices[index(x, y+1, z, width, height)],
-                            vertices[index(x+1, y, z, width, height)],
-                            vertices[index(x+1, y+1, z, width, height)],
-                        ),
-                        _ => unreachable!(),
-                    };
+        mesh_positions.push(v0.into());
+        mesh_positions.push(v2.into());
+        mesh_positions.push(v3.into());
 
-                    if inside[e.0] == (face == 1) {
-                        mesh_positions.push(v0.into());
-                        mesh_positions.push(v1.into());
-                        mesh_positions.push(v3.into());
+        let normal = face_normal(v0, v1, v2);
 
-                        mesh_positions.push(v0.into());
-                        mesh_positions.push(v3.into());
-                        mesh_positions.push(v2.into());
+        mesh_normals.push(normal.into());
+        mesh_normals.push(normal.into());
+        mesh_normals.push(normal.into());
 
-                        let normal = (v1 - v0).cross(v3 - v0).normalize();
+        let normal = face_normal(v0, v2, v3);
 
-                        mesh_normals.push(normal.into());
-                        mesh_normals.push(normal.into());
-                        mesh_normals.push(normal.into());
+        mesh_normals.push(normal.into());
+        mesh_normals.push(normal.into());
+        mesh_normals.push(normal.into());
+    }
+    Mesh::new(mesh_positions, mesh_normals)
+}
 
-                        let normal = (v3 - v0).cross(v2 - v0).normalize();
+/// Dual contouring producing an indexed mesh
+///
+/// Returns one vertex per cell containing the surface, with normals averaged
+/// from the adjacent faces. If `quads` is set, the mesh is made of quads, see
+/// [`Mesh::quads`], otherwise each quad is split into two triangles.
+pub fn dual_contouring_indexed<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    quads: bool,
+) -> Mesh {
+    let options = QefOptions::default();
+    extract_indexed(field, iso_level, quads, &options, ALL_CELLS, [0, 0, 0], normalize_scale(field))
+}
 
-                        mesh_normals.push(normal.into());
-                        mesh_normals.push(normal.into());
-                        mesh_normals.push(normal.into());
-                    }
-                    else {
-                        mesh_positions.push(v0.into());
-                        mesh_positions.push(v3.into());
-                        mesh_positions.push(v1.into());
+/// [`dual_contouring_indexed`], failing if `field` is invalid, see
+/// [`try_dual_contouring`]
+pub fn try_dual_contouring_indexed<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    quads: bool,
+) -> Result<Mesh, MeshingError> {
+    validate_field(field)?;
+    Ok(dual_contouring_indexed(field, iso_level, quads))
+}
+
+/// [`dual_contouring_indexed`] with the given options, see
+/// [`dual_contouring_with`]
+pub fn dual_contouring_indexed_with<F: ScalarField + ?Sized>(
+    field: &F,
+    quads: bool,
+    options: MeshingOptions,
+) -> Mesh {
+    let grid = options.grid(field);
+    let mesh = extract_indexed(field, options.iso_level, quads, &options.qef, ALL_CELLS, [0, 0, 0], grid.spacing);
+    options.finish(mesh, &grid)
+}
+
+/// Indexed version of [`extract`]
+pub(crate) fn extract_indexed<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    quads: bool,
+    options: &QefOptions,
+    cells: [usize; 3],
+    origin: [usize; 3],
+    scale: Vec3,
+) -> Mesh {
+    let (vertices, cell_to_vertex) = cell_vertices(field, iso_level, origin, scale, |cell| {
+        qef_vertex(field, iso_level, cell, options)
+    });
+
+    indexed_mesh(vertices, &cell_vertex_quads(field, iso_level, cells, &cell_to_vertex), quads)
+}
+
+/// Builds an indexed mesh from `vertices` and `cell_quads`, with normals
+/// averaged from the adjacent faces
+pub(crate) fn indexed_mesh(
+    vertices: Vec<Vec3>,
+    cell_quads: &[[u32; 4]],
+    quads: bool,
+) -> Mesh {
+    let mut vertex_normals = Vec::<Vec3>::new();
+    quad_normals(&vertices, cell_quads, &mut vertex_normals);
+    let mut mesh_indices = Vec::<u32>::new();
+
+    for &quad in cell_quads {
+        if quads {
+            mesh_indices.extend_from_slice(&quad);
+        } else {
+            mesh_indices.extend_from_slice(&[quad[0], quad[1], quad[2]]);
+            mesh_indices.extend_from_slice(&[quad[0], quad[2], quad[3]]);
+        }
+    }
+
+    let mesh_positions = vertices.into_iter().map(|v| v.into()).collect();
+    let mesh_normals = vertex_normals
+        .into_iter()
+        .map(|n| unit_normal(n).into())
+        .collect();
+
+    if quads {
+        Mesh::quads(mesh_positions, mesh_normals, mesh_indices)
+    } else {
+        Mesh::indexed(mesh_positions, mesh_normals, mesh_indices)
+    }
+}
+
+/// Sets `normals` to the unnormalized sum of the normals of the quads around
+/// each vertex
+pub(crate) fn quad_normals(vertices: &[Vec3], cell_quads: &[[u32; 4]], normals: &mut Vec<Vec3>) {
+    normals.clear();
+    normals.resize(vertices.len(), Vec3::ZERO);
+
+    for &quad in cell_quads {
+        let [v0, v1, v2, v3] = quad.map(|v| vertices[v as usize]);
+
+        // Not normalized, larger faces get a larger weight
+        let normal = (v1 - v0).cross(v2 - v0) + (v2 - v0).cross(v3 - v0);
+        for v in quad {
+            normals[v as usize] += normal;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
 
-                        mesh_positions.push(v0.into());
-                        mesh_positions.push(v2.into());
-                        mesh_positions.push(v3.into());
+    use super::*;
+
+    /// Signed distance to a sphere that can be evaluated anywhere, with its
+    /// gradient if `exact_gradient` is set
+    struct Sphere {
+        center: Vec3,
+        radius: f32,
+        exact_gradient: bool,
+    }
 
-                        let normal = (v3 - v0).cross(v1 - v3).normalize();
+    impl ScalarField for Sphere {
+        fn dims(&self) -> [usize; 3] {
+            [12; 3]
+        }
 
-                        mesh_normals.push(normal.into());
-                        mesh_normals.push(normal.into());
-                        mesh_normals.push(normal.into());
+        fn sample(&self, x: usize, y: usize, z: usize) -> f32 {
+            self.sample_at(Vec3::new(x as f32, y as f32, z as f32)).unwrap()
+        }
 
-                        let normal = (v2 - v0).cross(v3 - v0).normalize();
+        fn sample_at(&self, p: Vec3) -> Option<f32> {
+            Some((p - self.center).length() - self.radius)
+        }
 
-                        mesh_normals.push(normal.into());
-                        mesh_normals.push(normal.into());
-                        mesh_normals.push(normal.into());
+        fn gradient_at(&self, p: Vec3) -> Option<Vec3> {
+            self.exact_gradient.then(|| (p - self.center).normalize())
+        }
+    }
+
+    /// Crossings and normals on every edge of the field crossed by the
+    /// surface, in grid coordinates
+    fn crossings(field: &Sphere) -> Vec<(Vec3, Vec3)> {
+        let mut crossings = Vec::new();
+        for z in 0..11 {
+            for y in 0..11 {
+                for x in 0..11 {
+                    for c1 in [(1, 0, 0), (0, 1, 0), (0, 0, 1)] {
+                        if let Some((p, n)) = edge_crossing(field, 0.0, [x, y, z], (0, 0, 0), c1) {
+                            crossings.push((Vec3::new(x as f32, y as f32, z as f32) + p, n));
+                        }
                     }
                 }
             }
         }
+        crossings
+    }
+
+    #[test]
+    fn crossings_lie_on_the_surface() {
+        for (exact_gradient, min_cos) in [(true, 0.9999), (false, 0.99)] {
+            let field = Sphere { center: Vec3::new(5.6, 5.3, 5.1), radius: 3.7, exact_gradient };
+            let crossings = crossings(&field);
+            assert!(!crossings.is_empty());
+
+            for (p, n) in crossings {
+                assert!(field.sample_at(p).unwrap().abs() < 1e-4, "{p}");
+                let cos = n.dot((p - field.center).normalize());
+                assert!(cos > min_cos, "{p}: {n}");
+            }
+        }
     }
-    (mesh_positions, mesh_normals)
 }
@@ -0,0 +1,55 @@
+use std::fmt;
+
+/// Reason a field cannot be meshed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MeshingError {
+    /// The grid has fewer than two samples along some axis, so it has no
+    /// cells
+    TooFewSamples {
+        dims: [usize; 3],
+    },
+    /// The number of samples in the grid overflows `usize`
+    TooManySamples {
+        dims: [usize; 3],
+    },
+    /// A slice holds fewer values than the grid has samples
+    SliceTooShort {
+        /// Which slice, e.g. `"density"`
+        name: &'static str,
+        len: usize,
+        expected: usize,
+    },
+    /// A density is NaN or infinite
+    NonFiniteDensity {
+        position: [usize; 3],
+        value: f32,
+    },
+}
+
+impl fmt::Display for MeshingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooFewSamples { dims } => write!(
+                f,
+                "grid of {}x{}x{} samples has no cells, at least 2 are needed along each axis",
+                dims[0], dims[1], dims[2]
+            ),
+            Self::TooManySamples { dims } => write!(
+                f,
+                "grid of {}x{}x{} samples has more samples than fit in usize",
+                dims[0], dims[1], dims[2]
+            ),
+            Self::SliceTooShort { name, len, expected } => write!(
+                f,
+                "{name} slice holds {len} values but the grid has {expected} samples"
+            ),
+            Self::NonFiniteDensity { position, value } => write!(
+                f,
+                "density at ({}, {}, {}) is {value}",
+                position[0], position[1], position[2]
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MeshingError {}
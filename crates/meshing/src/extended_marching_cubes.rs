@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+
+use glam::Vec3;
+
+use crate::degenerate::{face_normal, unit_normal};
+use crate::field::{index, normalize_scale, ScalarField};
+use crate::marching_cubes_33::{
+    ambiguous, cell_crossings, cell_normals, cell_values, center, face_corners, face_resolution, table,
+    Triangulation,
+};
+use crate::mesh::Mesh;
+use crate::options::MeshingOptions;
+use crate::parallel::map_slabs;
+use crate::qef::{Qef, QefOptions};
+
+/// Feature vertex of the loop through each crossed edge of a cell, in grid
+/// coordinates
+type CellFeatures = [Option<Vec3>; 12];
+
+/// Finds the feature vertices of cell (x, y, z), `None` if it has none
+///
+/// Each loop of crossings whose normals differ by more than the feature angle
+/// gets a vertex at the minimizer of the QEF of the tangent planes at its
+/// crossings, placed in the cell as set by `options`. Angles and distances are
+/// those of the grid stretched by `scale`.
+#[allow(clippy::too_many_arguments)]
+fn cell_features<F: ScalarField + ?Sized>(
+    field: &F,
+    cos_feature_angle: f32,
+    options: &QefOptions,
+    scale: Vec3,
+    [x, y, z]: [usize; 3],
+    values: &[f32; 8],
+    case: usize,
+    crossings: &[Vec3; 12],
+) -> Option<CellFeatures> {
+    let table = table();
+    let cell = Vec3::new(x as f32, y as f32, z as f32);
+
+    // Normals at the crossings, where the surface is best known, from the
+    // field if it knows them and otherwise interpolated along the edges
+    let mut normals = cell_normals(field, table, values, case, [x, y, z], scale);
+    for (normal, &p) in normals.iter_mut().zip(crossings) {
+        if *normal != Vec3::ZERO {
+            if let Some(gradient) = field.gradient_at(p) {
+                *normal = unit_normal(gradient / scale);
+            }
+        }
+    }
+
+    let mut features = [None; 12];
+    let faces = face_resolution(values, case);
+    for edges in table.shape.loops(case, |f| faces & (1 << f) != 0) {
+        let sharp = edges.iter().enumerate().any(|(i, &e0)| {
+            edges[i + 1..].iter().any(|&e1| normals[e0 as usize].dot(normals[e1 as usize]) < cos_feature_angle)
+        });
+        if !sharp {
+            continue;
+        }
+
+        let mut qef = Qef::new();
+        for &e in &edges {
+            qef.add((crossings[e as usize] - cell) * scale, normals[e as usize]);
+        }
+
+        let (vertex, _) = qef.solve_in_cell(Vec3::ZERO, scale, options);
+        for &e in &edges {
+            features[e as usize] = Some(cell + vertex / scale);
+        }
+    }
+
+    features.iter().any(Option::is_some).then_some(features)
+}
+
+/// Implements L Kobbelt et al, Feature Sensitive Surface Extraction from
+/// Volume Data
+///
+/// Marching cubes that keeps sharp edges and corners. Where the normals of the
+/// crossings around a loop differ by more than `feature_angle`, in radians,
+/// the loop is fanned around a feature vertex placed with the same [`crate::Qef`] as
+/// [`crate::dual_contouring`]. Edges between the fans of neighbouring cells
+/// are then flipped to join their feature vertices, which lines the triangles
+/// up with the feature. Everywhere else the output matches
+/// [`crate::marching_cubes_with`] using [`crate::MarchingCubesMode::Mc33`]
+/// without the tunnel test.
+///
+/// The normals at the crossings come from [`ScalarField::gradient_at`] if the
+/// field supports it, otherwise from the gradients at the grid points, which
+/// blur the features and place their vertices less precisely. Output normals
+/// are per face, so the features stay sharp when shaded.
+pub fn extended_marching_cubes<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    feature_angle: f32,
+) -> Mesh {
+    extract(field, iso_level, feature_angle, &QefOptions::default(), normalize_scale(field))
+}
+
+/// [`extended_marching_cubes`] with the given options, including the
+/// placement of the feature vertices in their cells
+pub fn extended_marching_cubes_with<F: ScalarField + ?Sized>(
+    field: &F,
+    feature_angle: f32,
+    options: MeshingOptions,
+) -> Mesh {
+    let grid = options.grid(field);
+    let mesh = extract(field, options.iso_level, feature_angle, &options.qef, grid.spacing);
+    options.finish(mesh, &grid)
+}
+
+/// Extended marching cubes with vertices scaled by `scale`
+fn extract<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    feature_angle: f32,
+    options: &QefOptions,
+    scale: Vec3,
+) -> Mesh {
+    let [width, height, depth] = field.dims();
+    let table = table();
+    let cos_feature_angle = feature_angle.cos();
+
+    let features: HashMap<usize, CellFeatures> = map_slabs(depth - 1, |layers| {
+        let mut features = Vec::new();
+        for z in layers {
+            for y in 0..height-1 {
+                for x in 0..width-1 {
+                    let (values, case) = cell_values(field, iso_level, x, y, z);
+                    if case == 0 || case == 255 {
+                        continue;
+                    }
+
+                    let crossings = cell_crossings(table, &values, case, [0, 0, 0], x, y, z);
+                    if let Some(f) = cell_features(field, cos_feature_angle, options, scale, [x, y, z], &values, case, &crossings) {
+                        features.push((index(x, y, z, width, height), f));
+                    }
+                }
+            }
+        }
+        features
+    })
+    .into_iter()
+    .flatten()
+    .collect();
+
+    // Feature vertex of the loop through the crossing on edge `e0` of the
+    // neighbour of cell `p` across the face shared by edges `e0` and `e1`.
+    // Faces with two segments are left alone, as the loops through them may
+    // differ between the cells.
+    let neighbour_feature = |p: [usize; 3], case: usize, e0: u8, e1: u8| {
+        let edges = table.shape.edges();
+        let ((a, b), (c, d)) = (edges[e0 as usize], edges[e1 as usize]);
+        let axis = (0..3).find(|&axis| [b, c, d].iter().all(|&corner| (corner ^ a) & (1 << axis) == 0))?;
+        let bit = 1 << axis;
+        if ambiguous(case, face_corners(3 * ((a >> axis) & 1) + axis)) {
+            return None;
+        }
+
+        let mut neighbour = p;
+        if a & bit != 0 {
+            neighbour[axis] += 1;
+            if neighbour[axis] + 1 >= [width, height, depth][axis] {
+                return None;
+            }
+        } else {
+            neighbour[axis] = neighbour[axis].checked_sub(1)?;
+        }
+
+        let e = edges.iter().position(|&edge| edge == (a ^ bit, b ^ bit))?;
+        features.get(&index(neighbour[0], neighbour[1], neighbour[2], width, height))?[e]
+    };
+
+    let slabs = map_slabs(depth - 1, |layers| {
+        let mut mesh_vertices = Vec::<[f32;3]>::new();
+        let mut mesh_normals = Vec::<[f32;3]>::new();
+        let mut push_triangle = |v0: Vec3, v1: Vec3, v2: Vec3| {
+            let (v0, v1, v2) = (v0 * scale, v1 * scale, v2 * scale);
+            mesh_vertices.push(v0.into());
+            mesh_vertices.push(v1.into());
+            mesh_vertices.push(v2.into());
+
+            let normal = face_normal(v0, v1, v2);
+            mesh_normals.push(normal.into());
+            mesh_normals.push(normal.into());
+            mesh_normals.push(normal.into());
+        };
+
+        for z in layers {
+            for y in 0..height-1 {
+                for x in 0..width-1 {
+                    let (values, case) = cell_values(field, iso_level, x, y, z);
+                    if case == 0 || case == 255 {
+                        continue;
+                    }
+
+                    let crossings = cell_crossings(table, &values, case, [0, 0, 0], x, y, z);
+                    let faces = face_resolution(&values, case);
+                    let mut triangulation = Triangulation::default();
+                    let triangulation = match features.get(&index(x, y, z, width, height)) {
+                        None => &table.triangulations[64 * case + faces],
+                        Some(cell_features) => {
+                            for edges in table.shape.loops(case, |f| faces & (1 << f) != 0) {
+                                let Some(feature) = cell_features[edges[0] as usize] else {
+                                    triangulation.close(table.shape.edges(), &edges);
+                                    continue;
+                                };
+
+                                // Fan around the feature, facing the same way as
+                                // `Triangulation::close`, flipping the edge on the
+                                // face towards the neighbour's feature
+                                for (i, &e0) in edges.iter().enumerate() {
+                                    let e1 = edges[(i + 1) % edges.len()];
+                                    let third = neighbour_feature([x, y, z], case, e0, e1)
+                                        .unwrap_or(crossings[e0 as usize]);
+                                    push_triangle(feature, crossings[e1 as usize], third);
+                                }
+                            }
+                            &triangulation
+                        }
+                    };
+
+                    let vertex = |v: u8| match v {
+                        0..=11 => crossings[v as usize],
+                        _ => center(&crossings, &triangulation.centers[v as usize - 12]),
+                    };
+                    for &[i0, i1, i2] in &triangulation.triangles {
+                        push_triangle(vertex(i0), vertex(i1), vertex(i2));
+                    }
+                }
+            }
+        }
+        (mesh_vertices, mesh_normals)
+    });
+
+    let mut mesh_vertices = Vec::<[f32;3]>::new();
+    let mut mesh_normals = Vec::<[f32;3]>::new();
+    for (vertices, normals) in slabs {
+        mesh_vertices.extend(vertices);
+        mesh_normals.extend(normals);
+    }
+    Mesh::new(mesh_vertices, mesh_normals)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+    use crate::geometry::GridGeometry;
+    use crate::test_util::{boundary_edges, FnField};
+
+    /// Box with exact gradients, the maximum of the distances to the planes
+    /// of its faces
+    struct BoxField {
+        center: Vec3,
+        half_size: Vec3,
+    }
+
+    impl BoxField {
+        /// Distance to the nearest face plane and the axis of the face
+        fn face(&self, p: Vec3) -> (f32, usize) {
+            let q = (p - self.center).abs() - self.half_size;
+            let axis = (0..3).fold(0, |best, i| if q[i] > q[best] { i } else { best });
+            (q[axis], axis)
+        }
+
+        /// Point on corner `i`, x varying fastest
+        fn corner(&self, i: usize) -> Vec3 {
+            let sign = Vec3::new([-1.0, 1.0][i & 1], [-1.0, 1.0][(i >> 1) & 1], [-1.0, 1.0][i >> 2]);
+            self.center + sign * self.half_size
+        }
+    }
+
+    impl ScalarField for BoxField {
+        fn dims(&self) -> [usize; 3] {
+            [14; 3]
+        }
+
+        fn sample(&self, x: usize, y: usize, z: usize) -> f32 {
+            self.face(Vec3::new(x as f32, y as f32, z as f32)).0
+        }
+
+        fn gradient_at(&self, p: Vec3) -> Option<Vec3> {
+            let axis = self.face(p).1;
+            let mut gradient = Vec3::ZERO;
+            gradient[axis] = (p[axis] - self.center[axis]).signum();
+            Some(gradient)
+        }
+    }
+
+    #[test]
+    fn box_keeps_corners_and_edges() {
+        // Faces 0.8 past the nearest grid points inside, so that the samples
+        // of the edges crossing a face lie on the same side of the others
+        let field = BoxField { center: Vec3::new(6.0, 6.0, 5.0), half_size: Vec3::new(3.8, 2.8, 2.8) };
+        let options = MeshingOptions::new().geometry(GridGeometry::default()).bias_strength(0.0);
+        let mesh = extended_marching_cubes_with(&field, 0.5, options);
+        assert!(!mesh.is_empty());
+        assert_eq!(boundary_edges(&mesh), 0);
+
+        // Every vertex lies on the box, so the edges are kept, and a vertex
+        // lies on each corner
+        let vertices: Vec<Vec3> = mesh.positions.iter().map(|&p| p.into()).collect();
+        for &p in &vertices {
+            assert!(field.face(p).0.abs() < 1e-4, "{p}");
+        }
+        for i in 0..8 {
+            let corner = field.corner(i);
+            assert!(vertices.iter().any(|&p| (p - corner).length() < 1e-4), "{corner}");
+        }
+    }
+
+    #[test]
+    fn features_are_found_on_the_stretched_grid() {
+        // Roof whose slopes are shallow on the grid but steep once x is
+        // shrunk, the crossings all lie below the ridge at z = 5.6
+        let field = FnField { dims: [16, 4, 12], f: |[x, _, z]: [usize; 3]| z as f32 - 5.6 + 0.2 * (x as f32 - 8.3).abs() };
+        let top = |sx: f32| {
+            let geometry = GridGeometry::new(Vec3::ZERO, Vec3::new(sx, 1.0, 1.0));
+            let options = MeshingOptions::new().geometry(geometry).bias_strength(0.0);
+            let mesh = extended_marching_cubes_with(&field, 1.0, options);
+            mesh.positions.iter().map(|p| p[2]).fold(f32::MIN, f32::max)
+        };
+        assert!(top(1.0) < 5.545);
+        assert!(top(0.1) > 5.55);
+    }
+}
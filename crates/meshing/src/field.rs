@@ -0,0 +1,396 @@
+use glam::Vec3;
+
+use crate::error::MeshingError;
+
+/// Scalar field sampled on a regular grid
+///
+/// Implement this to mesh procedural or lazily evaluated fields without first
+/// storing every sample in a dense array. Fields are `Sync` so that the
+/// extractors can sample them from several threads.
+pub trait ScalarField: Sync {
+    /// Number of samples along x, y and z
+    fn dims(&self) -> [usize; 3];
+
+    /// Density at grid point (x, y, z)
+    fn sample(&self, x: usize, y: usize, z: usize) -> f32;
+
+    /// Density at grid point (x, y, z) minus `iso_level`
+    ///
+    /// The extractors classify and interpolate the samples with this. Fields
+    /// storing their densities at a higher precision than `f32` override it
+    /// to subtract before rounding, see [`Voxel::sub_iso`].
+    fn sample_relative(&self, x: usize, y: usize, z: usize, iso_level: f32) -> f32 {
+        self.sample(x, y, z) - iso_level
+    }
+
+    /// Gradient at grid point (x, y, z), if known
+    ///
+    /// Extractors needing normals fall back to central differences when this
+    /// returns `None`.
+    fn gradient(&self, _x: usize, _y: usize, _z: usize) -> Option<Vec3> {
+        None
+    }
+
+    /// Density at point `p` in grid coordinates, for fields that can be
+    /// evaluated between the grid points
+    ///
+    /// Dual contouring uses this to locate the crossings on the edges by root
+    /// finding rather than by interpolating the samples linearly.
+    fn sample_at(&self, _p: Vec3) -> Option<f32> {
+        None
+    }
+
+    /// Gradient at point `p` in grid coordinates, if known
+    ///
+    /// Dual contouring and extended marching cubes use this for the normals
+    /// at the crossings, falling back to interpolating the gradients at both
+    /// ends of the edge.
+    fn gradient_at(&self, _p: Vec3) -> Option<Vec3> {
+        None
+    }
+
+    /// Checks that every grid point can be sampled, e.g. that the backing
+    /// storage is large enough
+    ///
+    /// Called by [`validate_field`] before it samples the field, so that the
+    /// `try_` extractors fail rather than panic.
+    fn validate(&self) -> Result<(), MeshingError> {
+        Ok(())
+    }
+}
+
+impl<F: ScalarField + ?Sized> ScalarField for &F {
+    fn dims(&self) -> [usize; 3] {
+        (**self).dims()
+    }
+
+    fn sample(&self, x: usize, y: usize, z: usize) -> f32 {
+        (**self).sample(x, y, z)
+    }
+
+    fn sample_relative(&self, x: usize, y: usize, z: usize, iso_level: f32) -> f32 {
+        (**self).sample_relative(x, y, z, iso_level)
+    }
+
+    fn gradient(&self, x: usize, y: usize, z: usize) -> Option<Vec3> {
+        (**self).gradient(x, y, z)
+    }
+
+    fn sample_at(&self, p: Vec3) -> Option<f32> {
+        (**self).sample_at(p)
+    }
+
+    fn gradient_at(&self, p: Vec3) -> Option<Vec3> {
+        (**self).gradient_at(p)
+    }
+
+    fn validate(&self) -> Result<(), MeshingError> {
+        (**self).validate()
+    }
+}
+
+/// Numeric type of the samples of a [`DenseField`]
+///
+/// Samples are converted to `f32` as they are read, so volumes of any of
+/// these types are meshed in place. Every `u8`, `u16` and `i16` value is
+/// exactly representable as an `f32`, so classifying samples against a
+/// fractional iso level and interpolating between them loses nothing. `f64`
+/// samples are rounded to the nearest `f32` only after the iso level is
+/// subtracted, so densities differing by less than the `f32` precision at
+/// their magnitude are still told apart near the surface.
+pub trait Voxel: Copy + Sync {
+    fn to_f32(self) -> f32;
+
+    /// The sample minus `iso_level`, rounded to `f32` after subtracting
+    fn sub_iso(self, iso_level: f32) -> f32 {
+        self.to_f32() - iso_level
+    }
+}
+
+macro_rules! impl_voxel {
+    ($($t:ty),*) => {
+        $(
+            impl Voxel for $t {
+                fn to_f32(self) -> f32 {
+                    self as f32
+                }
+            }
+        )*
+    };
+}
+
+impl_voxel!(u8, u16, i16, f32);
+
+impl Voxel for f64 {
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    fn sub_iso(self, iso_level: f32) -> f32 {
+        (self - iso_level as f64) as f32
+    }
+}
+
+/// Field stored as a dense slice, x varying fastest
+pub struct DenseField<'a, T = f32> {
+    density: &'a [T],
+    normal: Option<&'a [Vec3]>,
+    width: usize,
+    height: usize,
+    depth: usize,
+}
+
+impl<'a, T: Voxel> DenseField<'a, T> {
+    /// Field of `width * height * depth` samples from `density`
+    ///
+    /// Sampling panics if `density` is too short, see [`DenseField::try_new`].
+    /// The `try_` extractors check it before sampling, see [`validate_field`].
+    pub fn new(density: &'a [T], width: usize, height: usize, depth: usize) -> Self {
+        Self {
+            density,
+            normal: None,
+            width,
+            height,
+            depth,
+        }
+    }
+
+    /// [`DenseField::new`], failing if `density` holds fewer than
+    /// `width * height * depth` values
+    pub fn try_new(density: &'a [T], width: usize, height: usize, depth: usize) -> Result<Self, MeshingError> {
+        let field = Self::new(density, width, height, depth);
+        field.check_len("density", density.len())?;
+        Ok(field)
+    }
+
+    /// Uses `normal`, laid out like the densities, as the field gradient
+    pub fn with_normals(mut self, normal: &'a [Vec3]) -> Self {
+        self.normal = Some(normal);
+        self
+    }
+
+    /// [`DenseField::with_normals`], failing if `normal` holds fewer values
+    /// than the grid has samples
+    pub fn try_with_normals(self, normal: &'a [Vec3]) -> Result<Self, MeshingError> {
+        self.check_len("normal", normal.len())?;
+        Ok(self.with_normals(normal))
+    }
+
+    fn check_len(&self, name: &'static str, len: usize) -> Result<(), MeshingError> {
+        let expected = num_samples(self.dims())?;
+        if len < expected {
+            return Err(MeshingError::SliceTooShort { name, len, expected });
+        }
+        Ok(())
+    }
+}
+
+impl<T: Voxel> ScalarField for DenseField<'_, T> {
+    fn dims(&self) -> [usize; 3] {
+        [self.width, self.height, self.depth]
+    }
+
+    fn sample(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.density[index(x, y, z, self.width, self.height)].to_f32()
+    }
+
+    fn sample_relative(&self, x: usize, y: usize, z: usize, iso_level: f32) -> f32 {
+        self.density[index(x, y, z, self.width, self.height)].sub_iso(iso_level)
+    }
+
+    fn gradient(&self, x: usize, y: usize, z: usize) -> Option<Vec3> {
+        self.normal.map(|normal| normal[index(x, y, z, self.width, self.height)])
+    }
+
+    fn validate(&self) -> Result<(), MeshingError> {
+        self.check_len("density", self.density.len())?;
+        if let Some(normal) = self.normal {
+            self.check_len("normal", normal.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that `field` can be meshed
+///
+/// The grid needs at least two samples along each axis, no more samples in
+/// total than fit in `usize`, the field must pass [`ScalarField::validate`]
+/// and every density must be finite. Samples the
+/// whole field, so is as costly as meshing it once more for procedural
+/// fields.
+pub fn validate_field<F: ScalarField + ?Sized>(field: &F) -> Result<(), MeshingError> {
+    let dims = field.dims();
+    if dims.iter().any(|&n| n < 2) {
+        return Err(MeshingError::TooFewSamples { dims });
+    }
+    num_samples(dims)?;
+    field.validate()?;
+
+    let [width, height, depth] = dims;
+    for z in 0..depth {
+        for y in 0..height {
+            for x in 0..width {
+                let value = field.sample(x, y, z);
+                if !value.is_finite() {
+                    return Err(MeshingError::NonFiniteDensity { position: [x, y, z], value });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Number of samples in a grid of `dims`, failing if it overflows
+fn num_samples(dims: [usize; 3]) -> Result<usize, MeshingError> {
+    dims.iter()
+        .try_fold(1_usize, |n, &len| n.checked_mul(len))
+        .ok_or(MeshingError::TooManySamples { dims })
+}
+
+/// Scale mapping the grid of `field` to [0, 1)
+pub(crate) fn normalize_scale<F: ScalarField + ?Sized>(field: &F) -> Vec3 {
+    let [width, height, depth] = field.dims();
+    Vec3::new(
+        1.0 / width as f32,
+        1.0 / height as f32,
+        1.0 / depth as f32,
+    )
+}
+
+pub(crate) fn index(x: usize, y: usize, z: usize, width: usize, height: usize) -> usize {
+    x + y * width + z * width * height
+}
+
+/// Gradient of `field` at grid point (x, y, z)
+///
+/// Uses [`ScalarField::gradient`] if available, otherwise central differences
+/// (one-sided at the borders).
+pub(crate) fn gradient<F: ScalarField + ?Sized>(field: &F, x: usize, y: usize, z: usize) -> Vec3 {
+    if let Some(gradient) = field.gradient(x, y, z) {
+        return gradient;
+    }
+
+    let [width, height, depth] = field.dims();
+    let difference = |p0: (usize, usize, usize), p1: (usize, usize, usize), steps: usize| {
+        (field.sample(p1.0, p1.1, p1.2) - field.sample(p0.0, p0.1, p0.2)) / steps.max(1) as f32
+    };
+
+    let (x0, x1) = (x.saturating_sub(1), (x + 1).min(width - 1));
+    let (y0, y1) = (y.saturating_sub(1), (y + 1).min(height - 1));
+    let (z0, z1) = (z.saturating_sub(1), (z + 1).min(depth - 1));
+
+    Vec3::new(
+        difference((x0, y, z), (x1, y, z), x1 - x0),
+        difference((x, y0, z), (x, y1, z), y1 - y0),
+        difference((x, y, z0), (x, y, z1), z1 - z0),
+    )
+}
+
+/// Maximum number of false position steps taken by [`edge_root`]
+const ROOT_STEPS: usize = 16;
+
+/// Width of the bracket, relative to the edge, at which [`edge_root`] stops
+const ROOT_TOLERANCE: f32 = 1e-5;
+
+/// Fraction of the way from `p0` to `p1`, with densities `v0` and `v1`
+/// relative to `iso_level`, at which the field crosses `iso_level`
+///
+/// Refines the linear interpolation of the densities with the Illinois
+/// variant of false position if the field supports
+/// [`ScalarField::sample_at`].
+pub(crate) fn edge_root<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    (p0, v0): (Vec3, f32),
+    (p1, v1): (Vec3, f32),
+) -> f32 {
+    let (mut a, mut fa) = (0.0, v0);
+    let (mut b, mut fb) = (1.0, v1);
+    // Side the previous step replaced, to halve the value kept from the
+    // other side if it is replaced twice in a row
+    let mut side = 0;
+
+    for _ in 0..ROOT_STEPS {
+        if b - a <= ROOT_TOLERANCE {
+            break;
+        }
+
+        let t = (a * fb - b * fa) / (fb - fa);
+        let Some(density) = field.sample_at(p0.lerp(p1, t)) else {
+            return t;
+        };
+
+        let ft = density - iso_level;
+        if ft == 0.0 {
+            return t;
+        } else if (ft > 0.0) == (fb > 0.0) {
+            (b, fb) = (t, ft);
+            if side == 1 {
+                fa *= 0.5;
+            }
+            side = 1;
+        } else {
+            (a, fa) = (t, ft);
+            if side == -1 {
+                fb *= 0.5;
+            }
+            side = -1;
+        }
+    }
+    (a * fb - b * fa) / (fb - fa)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::GridGeometry;
+    use crate::options::MeshingOptions;
+    use crate::{dual_contouring_with, marching_cubes_with, try_dual_contouring, try_marching_cubes};
+
+    #[test]
+    fn short_slices_fail_validation() {
+        let density = vec![0.5_f32; 27];
+        let normal = vec![Vec3::Z; 27];
+
+        let short = DenseField::new(&density[..26], 3, 3, 3);
+        let expected = MeshingError::SliceTooShort { name: "density", len: 26, expected: 27 };
+        assert_eq!(validate_field(&short), Err(expected));
+        assert_eq!(try_marching_cubes(&short, 0.0), Err(expected));
+        assert_eq!(try_dual_contouring(&short, 0.0), Err(expected));
+
+        let short_normals = DenseField::new(&density, 3, 3, 3).with_normals(&normal[..1]);
+        let expected = MeshingError::SliceTooShort { name: "normal", len: 1, expected: 27 };
+        assert_eq!(try_dual_contouring(&short_normals, 0.0), Err(expected));
+
+        let field = DenseField::new(&density, 3, 3, 3).with_normals(&normal);
+        assert_eq!(validate_field(&field), Ok(()));
+    }
+
+    #[test]
+    fn huge_grids_fail_validation() {
+        let density = vec![0.5_f32; 27];
+        let dims = [usize::MAX / 2, 3, 3];
+        let expected = MeshingError::TooManySamples { dims };
+        assert_eq!(DenseField::try_new(&density, dims[0], dims[1], dims[2]).err(), Some(expected));
+
+        let field = DenseField::new(&density, dims[0], dims[1], dims[2]);
+        assert_eq!(validate_field(&field), Err(expected));
+        assert_eq!(try_marching_cubes(&field, 0.0), Err(expected));
+        assert_eq!(try_dual_contouring(&field, 0.0), Err(expected));
+    }
+
+    #[test]
+    fn f64_samples_are_offset_before_rounding() {
+        // Both samples round to the iso level as f32
+        let iso_level = 1e8;
+        let density: Vec<f64> = (0..64).map(|i| iso_level + if i % 4 < 2 { -0.25 } else { 0.75 }).collect();
+        let field = DenseField::new(&density, 4, 4, 4);
+        assert_eq!(field.sample(1, 0, 0), field.sample(2, 0, 0));
+
+        let options = MeshingOptions::new().iso_level(iso_level as f32).geometry(GridGeometry::default());
+        for mesh in [marching_cubes_with(&field, options), dual_contouring_with(&field, options)] {
+            assert!(!mesh.positions.is_empty());
+            assert!(mesh.positions.iter().all(|p| (p[0] - 1.25).abs() < 1e-6));
+        }
+    }
+}
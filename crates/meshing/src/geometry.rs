@@ -0,0 +1,107 @@
+use glam::{Affine3A, Mat3, Vec3};
+
+use crate::field::{normalize_scale, ScalarField};
+
+/// Placement of the grid samples in space
+///
+/// Grid point (x, y, z) lies at `origin + direction * ((x, y, z) * spacing)`.
+/// The default places it at (x, y, z), i.e. outputs grid coordinates. Volumes
+/// with anisotropic voxels, such as medical scans, get undistorted meshes in
+/// physical units by giving the voxel size as the spacing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GridGeometry {
+    /// Position of grid point (0, 0, 0)
+    pub origin: Vec3,
+    /// Distance between neighbouring grid points along x, y and z
+    pub spacing: Vec3,
+    /// Orientation of the grid, the columns being the directions of its x, y
+    /// and z axes, or `None` for the identity
+    pub direction: Option<Mat3>,
+}
+
+impl Default for GridGeometry {
+    fn default() -> Self {
+        Self {
+            origin: Vec3::ZERO,
+            spacing: Vec3::ONE,
+            direction: None,
+        }
+    }
+}
+
+impl GridGeometry {
+    pub fn new(origin: Vec3, spacing: Vec3) -> Self {
+        Self {
+            origin,
+            spacing,
+            direction: None,
+        }
+    }
+
+    /// Geometry scaling the grid of `field` into [0, 1), dividing by the
+    /// number of samples along each axis
+    ///
+    /// The extractors use this unless given a geometry. The last grid point
+    /// along each axis lies short of 1, see [`GridGeometry::unit`] for one
+    /// spanning [0, 1] exactly.
+    pub fn normalized<F: ScalarField + ?Sized>(field: &F) -> Self {
+        Self::new(Vec3::ZERO, normalize_scale(field))
+    }
+
+    /// Geometry scaling the grid of `field` into [0, 1], with the last grid
+    /// point along each axis at 1
+    pub fn unit<F: ScalarField + ?Sized>(field: &F) -> Self {
+        let [width, height, depth] = field.dims();
+        let cells = Vec3::new(width as f32, height as f32, depth as f32) - 1.0;
+        Self::new(Vec3::ZERO, Vec3::ONE / cells.max(Vec3::ONE))
+    }
+
+    pub fn with_direction(mut self, direction: Mat3) -> Self {
+        self.direction = Some(direction);
+        self
+    }
+
+    /// Position of point `p` in grid coordinates
+    pub fn to_world(&self, p: Vec3) -> Vec3 {
+        self.origin + self.direction.unwrap_or(Mat3::IDENTITY) * (p * self.spacing)
+    }
+
+    /// Transform applied after the spacing, `None` if it is the identity
+    pub(crate) fn placement(&self) -> Option<Affine3A> {
+        if self.origin == Vec3::ZERO && self.direction.is_none() {
+            return None;
+        }
+        Some(Affine3A::from_mat3_translation(
+            self.direction.unwrap_or(Mat3::IDENTITY),
+            self.origin,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{signed_volume, soup, sphere};
+    use crate::{dual_contouring_indexed_with, marching_cubes_indexed_with, marching_cubes_with, MeshingOptions};
+
+    #[test]
+    fn mirrored_placement_faces_outwards() {
+        let center = Vec3::new(5.6, 5.3, 5.8);
+        let field = sphere([12; 3], center, 3.9);
+        let mirror = Mat3::from_rotation_z(0.5) * Mat3::from_diagonal(Vec3::new(-1.0, 1.0, 1.0));
+        let geometry = GridGeometry::new(Vec3::new(1.0, -2.0, 3.0), Vec3::splat(0.5)).with_direction(mirror);
+        let world_center = geometry.to_world(center);
+
+        let options = MeshingOptions::new().geometry(geometry);
+        for mesh in [
+            marching_cubes_with(&field, options),
+            marching_cubes_indexed_with(&field, options),
+            dual_contouring_indexed_with(&field, true, options),
+        ] {
+            assert!(signed_volume(&soup(&mesh)) > 0.0);
+            for (&p, &n) in mesh.positions.iter().zip(&mesh.normals) {
+                assert!(Vec3::from(n).dot(Vec3::from(p) - world_center) > 0.0);
+            }
+        }
+    }
+}
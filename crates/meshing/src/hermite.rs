@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+
+use glam::Vec3;
+
+use crate::dual_contouring::{
+    cell_vertex_quads, cell_vertices, edge_crossing, indexed_mesh, soup_mesh, ALL_CELLS,
+};
+use crate::error::MeshingError;
+use crate::field::{index, DenseField, ScalarField, Voxel};
+use crate::mesh::Mesh;
+use crate::options::MeshingOptions;
+use crate::parallel::map_slabs;
+use crate::qef::{Qef, QefOptions};
+
+/// Crossing of the surface on a grid edge
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EdgeCrossing {
+    /// Fraction of the way from the lower to the upper end of the edge
+    pub t: f32,
+    /// Unit surface normal at the crossing
+    pub normal: Vec3,
+}
+
+/// Hermite data of a grid, which grid points are inside and where the surface
+/// crosses the edges between them
+///
+/// Dual contouring needs nothing else, so the data may be computed once with
+/// [`HermiteData::from_field`], kept and edited between remeshes, or filled
+/// in from other sources such as the voxelization of a mesh. Edges are
+/// identified by their lower grid point and their axis, 0 for x, 1 for y and
+/// 2 for z.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HermiteData {
+    dims: [usize; 3],
+    inside: Vec<bool>,
+    /// Crossings keyed by `3 * index(lower point) + axis`
+    crossings: HashMap<usize, EdgeCrossing>,
+}
+
+impl HermiteData {
+    /// Grid with `dims` samples along x, y and z, all outside and without
+    /// crossings
+    pub fn new(dims: [usize; 3]) -> Self {
+        Self {
+            dims,
+            inside: vec![false; dims[0] * dims[1] * dims[2]],
+            crossings: HashMap::new(),
+        }
+    }
+
+    /// Samples `field` at every grid point and edge
+    ///
+    /// Samples with a density at or below `iso_level` are inside. Crossings
+    /// and their normals are found as by [`crate::dual_contouring`].
+    pub fn from_field<F: ScalarField + ?Sized>(field: &F, iso_level: f32) -> Self {
+        let dims = field.dims();
+        let [width, height, depth] = dims;
+
+        let slabs = map_slabs(depth, |layers| {
+            let mut inside = Vec::with_capacity(width * height * layers.len());
+            let mut crossings = Vec::new();
+
+            for z in layers {
+                for y in 0..height {
+                    for x in 0..width {
+                        inside.push(field.sample_relative(x, y, z, iso_level) <= 0.0);
+
+                        for (axis, c1) in [(1, 0, 0), (0, 1, 0), (0, 0, 1)].into_iter().enumerate() {
+                            if [x, y, z][axis] + 1 >= dims[axis] {
+                                continue;
+                            }
+
+                            if let Some((p, normal)) = edge_crossing(field, iso_level, [x, y, z], (0, 0, 0), c1) {
+                                let key = 3 * index(x, y, z, width, height) + axis;
+                                crossings.push((key, EdgeCrossing { t: p[axis], normal }));
+                            }
+                        }
+                    }
+                }
+            }
+            (inside, crossings)
+        });
+
+        let mut data = Self {
+            dims,
+            inside: Vec::with_capacity(width * height * depth),
+            crossings: HashMap::new(),
+        };
+        for (inside, crossings) in slabs {
+            data.inside.extend(inside);
+            data.crossings.extend(crossings);
+        }
+        data
+    }
+
+    /// Samples a dense density array and its normals, laid out as in
+    /// [`DenseField`]
+    ///
+    /// Fails if either slice holds fewer values than the grid has samples.
+    pub fn from_dense<T: Voxel>(
+        density: &[T],
+        normal: &[Vec3],
+        width: usize,
+        height: usize,
+        depth: usize,
+        iso_level: f32,
+    ) -> Result<Self, MeshingError> {
+        let field = DenseField::try_new(density, width, height, depth)?.try_with_normals(normal)?;
+        Ok(Self::from_field(&field, iso_level))
+    }
+
+    /// Number of samples along x, y and z
+    pub fn dims(&self) -> [usize; 3] {
+        self.dims
+    }
+
+    pub fn is_inside(&self, [x, y, z]: [usize; 3]) -> bool {
+        self.inside[index(x, y, z, self.dims[0], self.dims[1])]
+    }
+
+    pub fn set_inside(&mut self, [x, y, z]: [usize; 3], inside: bool) {
+        self.inside[index(x, y, z, self.dims[0], self.dims[1])] = inside;
+    }
+
+    /// Crossing on the edge from grid point `p` along `axis`
+    pub fn crossing(&self, p: [usize; 3], axis: usize) -> Option<&EdgeCrossing> {
+        self.crossings.get(&self.key(p, axis))
+    }
+
+    /// Sets or, with `None`, removes the crossing on the edge from grid point
+    /// `p` along `axis`
+    ///
+    /// The inside flags at the ends of the edge are left as they are, edges
+    /// between points that are both inside or both outside are ignored when
+    /// contouring.
+    pub fn set_crossing(&mut self, p: [usize; 3], axis: usize, crossing: Option<EdgeCrossing>) {
+        let key = self.key(p, axis);
+        match crossing {
+            Some(crossing) => self.crossings.insert(key, crossing),
+            None => self.crossings.remove(&key),
+        };
+    }
+
+    /// All crossings with the lower grid point and axis of their edge, in no
+    /// particular order
+    pub fn crossings(&self) -> impl Iterator<Item = ([usize; 3], usize, &EdgeCrossing)> {
+        let [width, height, _] = self.dims;
+        self.crossings.iter().map(move |(&key, crossing)| {
+            let (i, axis) = (key / 3, key % 3);
+            ([i % width, i / width % height, i / (width * height)], axis, crossing)
+        })
+    }
+
+    fn key(&self, [x, y, z]: [usize; 3], axis: usize) -> usize {
+        assert!(axis < 3 && [x, y, z][axis] + 1 < self.dims[axis], "edge out of bounds");
+        3 * index(x, y, z, self.dims[0], self.dims[1]) + axis
+    }
+
+    /// QEF of the crossings on the edges of `cell` whose ends differ, relative
+    /// to the cell
+    fn cell_qef(&self, cell: [usize; 3]) -> Qef {
+        let mut qef = Qef::new();
+        for axis in 0..3 {
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            for (du, dv) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let mut offset = [0; 3];
+                offset[u] = du;
+                offset[v] = dv;
+
+                let p = [0, 1, 2].map(|i| cell[i] + offset[i]);
+                let mut q = p;
+                q[axis] += 1;
+                if self.is_inside(p) == self.is_inside(q) {
+                    continue;
+                }
+
+                if let Some(crossing) = self.crossing(p, axis) {
+                    let mut position = Vec3::new(offset[0] as f32, offset[1] as f32, offset[2] as f32);
+                    position[axis] = crossing.t;
+                    qef.add(position, crossing.normal);
+                }
+            }
+        }
+        qef
+    }
+
+    /// Vertex of `cell` relative to it, the center of the cell if none of its
+    /// edges has a crossing
+    fn vertex(&self, cell: [usize; 3], options: &QefOptions) -> Vec3 {
+        let qef = self.cell_qef(cell);
+        if qef.is_empty() {
+            return Vec3::splat(0.5);
+        }
+        qef.solve_in_cell(Vec3::ZERO, Vec3::ONE, options).0
+    }
+}
+
+/// The inside flags of [`HermiteData`] as a field with the surface at 0
+struct Signs<'a>(&'a HermiteData);
+
+impl ScalarField for Signs<'_> {
+    fn dims(&self) -> [usize; 3] {
+        self.0.dims
+    }
+
+    fn sample(&self, x: usize, y: usize, z: usize) -> f32 {
+        if self.0.is_inside([x, y, z]) { -1.0 } else { 1.0 }
+    }
+}
+
+/// [`crate::dual_contouring_with`] on precomputed Hermite data
+///
+/// The iso level of `options` is ignored, the data already tells which grid
+/// points are inside.
+pub fn dual_contouring_hermite(
+    data: &HermiteData,
+    options: MeshingOptions,
+) -> Mesh {
+    let field = Signs(data);
+    let grid = options.grid(&field);
+    let (vertices, cell_to_vertex) = cell_vertices(&field, 0.0, [0, 0, 0], grid.spacing, |cell| {
+        Some(data.vertex(cell, &options.qef))
+    });
+
+    let mesh = soup_mesh(&vertices, &cell_vertex_quads(&field, 0.0, ALL_CELLS, &cell_to_vertex));
+    options.finish(mesh, &grid)
+}
+
+/// [`crate::dual_contouring_indexed_with`] on precomputed Hermite data
+pub fn dual_contouring_hermite_indexed(
+    data: &HermiteData,
+    quads: bool,
+    options: MeshingOptions,
+) -> Mesh {
+    let field = Signs(data);
+    let grid = options.grid(&field);
+    let (vertices, cell_to_vertex) = cell_vertices(&field, 0.0, [0, 0, 0], grid.spacing, |cell| {
+        Some(data.vertex(cell, &options.qef))
+    });
+
+    let mesh = indexed_mesh(vertices, &cell_vertex_quads(&field, 0.0, ALL_CELLS, &cell_to_vertex), quads);
+    options.finish(mesh, &grid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_dense_checks_slice_lengths() {
+        let density = vec![0.0_f32; 27];
+        let normal = vec![Vec3::Z; 27];
+
+        assert!(HermiteData::from_dense(&density, &normal, 3, 3, 3, 0.0).is_ok());
+        assert_eq!(
+            HermiteData::from_dense(&density[..26], &normal, 3, 3, 3, 0.0),
+            Err(MeshingError::SliceTooShort { name: "density", len: 26, expected: 27 }),
+        );
+        assert_eq!(
+            HermiteData::from_dense(&density, &normal[..20], 3, 3, 3, 0.0),
+            Err(MeshingError::SliceTooShort { name: "normal", len: 20, expected: 27 }),
+        );
+    }
+}
@@ -1,6 +1,55 @@
+mod cell_contour;
+mod chunk;
+mod degenerate;
 mod dual_contouring;
+mod error;
+mod extended_marching_cubes;
+mod field;
+mod geometry;
+mod hermite;
+mod manifold;
 mod marching_cubes;
+mod marching_cubes_33;
+mod marching_tetrahedra;
+mod mesh;
+mod octree;
+mod options;
+mod parallel;
+mod qef;
+mod sink;
+mod surface_nets;
+#[cfg(test)]
+mod test_util;
+mod transvoxel;
 
-pub use dual_contouring::dual_contouring;
-pub use marching_cubes::marching_cubes;
-
+pub use chunk::{
+    dual_contouring_chunk, dual_contouring_chunk_indexed, dual_contouring_chunk_indexed_with,
+    dual_contouring_chunk_with, marching_cubes_chunk, marching_cubes_chunk_indexed,
+    marching_cubes_chunk_indexed_with, marching_cubes_chunk_with, Chunk,
+};
+pub use degenerate::{remove_degenerate_indexed, remove_degenerate_triangles};
+pub use dual_contouring::{
+    dual_contouring, dual_contouring_indexed, dual_contouring_indexed_with, dual_contouring_with,
+    try_dual_contouring, try_dual_contouring_indexed,
+};
+pub use error::MeshingError;
+pub use extended_marching_cubes::{extended_marching_cubes, extended_marching_cubes_with};
+pub use field::{validate_field, DenseField, ScalarField, Voxel};
+pub use geometry::GridGeometry;
+pub use hermite::{dual_contouring_hermite, dual_contouring_hermite_indexed, EdgeCrossing, HermiteData};
+pub use manifold::{dual_contouring_manifold, dual_contouring_manifold_with};
+pub use marching_cubes::{
+    marching_cubes, marching_cubes_indexed, marching_cubes_indexed_with, marching_cubes_with,
+    try_marching_cubes, try_marching_cubes_indexed, MarchingCubesMode, NormalMode,
+};
+pub use marching_tetrahedra::{
+    marching_tetrahedra, marching_tetrahedra_indexed, marching_tetrahedra_indexed_with,
+    marching_tetrahedra_with,
+};
+pub use mesh::Mesh;
+pub use octree::{dual_contouring_octree, dual_contouring_octree_with};
+pub use options::MeshingOptions;
+pub use qef::{Qef, QefOptions};
+pub use sink::{dual_contouring_into, marching_cubes_into, MeshSink, MeshingScratch};
+pub use surface_nets::{surface_nets, surface_nets_indexed, surface_nets_indexed_with, surface_nets_with};
+pub use transvoxel::{transvoxel, transvoxel_with, TransitionFaces};
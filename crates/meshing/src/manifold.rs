@@ -0,0 +1,438 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use glam::Vec3;
+
+use crate::cell_contour::CellShape;
+use crate::dual_contouring::{
+    cell_quads, edge_crossing, has_quad, indexed_mesh, ALL_CELLS, CORNERS, NO_VERTEX,
+};
+use crate::field::{index, normalize_scale, ScalarField};
+use crate::mesh::Mesh;
+use crate::options::MeshingOptions;
+use crate::parallel::map_slabs;
+use crate::qef::{Qef, QefOptions};
+
+/// Surface components of a cell for each sign configuration of its corners
+struct ComponentTable {
+    shape: CellShape,
+    /// Crossed edges of every component, per case, with the positive corners
+    /// of ambiguous faces connected
+    components: Vec<Vec<Vec<u8>>>,
+    /// Component each crossed edge belongs to, per case
+    edge_component: Vec<[u8; 12]>,
+}
+
+impl ComponentTable {
+    /// Edge between the given corners
+    fn edge(&self, corners: (usize, usize)) -> usize {
+        self.shape.edges().iter().position(|&e| e == corners).unwrap()
+    }
+}
+
+fn component_table() -> &'static ComponentTable {
+    static TABLE: OnceLock<ComponentTable> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let shape = CellShape::cube(&CORNERS);
+        let components: Vec<Vec<Vec<u8>>> = (0..256)
+            .map(|case| shape.loops(case, |_| false))
+            .collect();
+
+        let edge_component = components
+            .iter()
+            .map(|loops| edge_map(loops))
+            .collect();
+
+        ComponentTable {
+            shape,
+            components,
+            edge_component,
+        }
+    })
+}
+
+/// Maps each edge in `groups` to the index of its group
+fn edge_map(groups: &[Vec<u8>]) -> [u8; 12] {
+    let mut map = [u8::MAX; 12];
+    for (i, edges) in groups.iter().enumerate() {
+        for &e in edges {
+            map[e as usize] = i as u8;
+        }
+    }
+    map
+}
+
+/// Splits `edges` into runs of consecutive edges for which `has_quad` holds
+fn runs(edges: &[u8], has_quad: impl Fn(u8) -> bool) -> Vec<Vec<u8>> {
+    let Some(start) = edges.iter().position(|&e| !has_quad(e)) else {
+        return vec![edges.to_vec()];
+    };
+
+    let mut runs = Vec::new();
+    let mut run = Vec::new();
+    for i in 0..edges.len() {
+        let e = edges[(start + i) % edges.len()];
+        if has_quad(e) {
+            run.push(e);
+        } else if !run.is_empty() {
+            runs.push(std::mem::take(&mut run));
+        }
+    }
+    if !run.is_empty() {
+        runs.push(run);
+    }
+    runs
+}
+
+/// Manifold dual contouring, after S Schaefer, et al., Manifold Dual Contouring
+///
+/// Like [`crate::dual_contouring_indexed`], but places one vertex per surface
+/// component of a cell rather than one per cell. The components are the
+/// marching cubes patches of the cell. Ambiguous faces are resolved the same
+/// way from both sides, connecting the negative corners where the default
+/// would give a pair of cells sharing two patches. Components cut apart by
+/// the border of the field get a vertex per piece.
+///
+/// The output is a 2-manifold, possibly with boundary: every edge is shared by
+/// at most two faces and the faces around every vertex form a single fan.
+/// Vertices of different components of a cell may still end up at the same
+/// position.
+pub fn dual_contouring_manifold<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    quads: bool,
+) -> Mesh {
+    extract(field, iso_level, quads, &QefOptions::default(), normalize_scale(field))
+}
+
+/// [`dual_contouring_manifold`] with the given options, see
+/// [`crate::dual_contouring_with`]
+pub fn dual_contouring_manifold_with<F: ScalarField + ?Sized>(
+    field: &F,
+    quads: bool,
+    options: MeshingOptions,
+) -> Mesh {
+    let grid = options.grid(field);
+    let mesh = extract(field, options.iso_level, quads, &options.qef, grid.spacing);
+    options.finish(mesh, &grid)
+}
+
+/// Manifold dual contouring with vertices scaled by `scale`
+fn extract<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    quads: bool,
+    options: &QefOptions,
+    scale: Vec3,
+) -> Mesh {
+    let mut mesher = Mesher {
+        field,
+        iso_level,
+        options,
+        dims: field.dims(),
+        table: component_table(),
+        flips: HashSet::new(),
+    };
+    mesher.resolve_faces();
+
+    let (vertices, cell_to_vertex, cell_cases, edge_vertices) =
+        mesher.cell_vertices([0, 0, 0], scale);
+
+    let table = mesher.table;
+    let [width, height, _] = mesher.dims;
+    let cell_quads = cell_quads(field, iso_level, ALL_CELLS, |[x, y, z], corners| {
+        let cell = index(x, y, z, width, height);
+        let e = table.edge(corners);
+        let offset = match edge_vertices.get(&cell) {
+            Some(map) => map[e],
+            None => table.edge_component[cell_cases[cell] as usize][e],
+        };
+        cell_to_vertex[cell] + offset as u32
+    });
+
+    indexed_mesh(vertices, &cell_quads, quads)
+}
+
+struct Mesher<'a, F: ?Sized> {
+    field: &'a F,
+    iso_level: f32,
+    /// Placement of the vertices in their cells
+    options: &'a QefOptions,
+    dims: [usize; 3],
+    table: &'static ComponentTable,
+    /// Ambiguous faces with their negative corners connected, keyed by
+    /// [`Mesher::face_key`]
+    flips: HashSet<usize>,
+}
+
+impl<F: ScalarField + ?Sized> Mesher<'_, F> {
+    fn positive(&self, p: [usize; 3]) -> bool {
+        self.field.sample_relative(p[0], p[1], p[2], self.iso_level) > 0.0
+    }
+
+    /// Sign configuration of `cell`, bit `i` is set if corner `i` is positive
+    fn case(&self, cell: [usize; 3]) -> usize {
+        let mut case = 0;
+        for (i, c) in CORNERS.iter().enumerate() {
+            if self.positive([cell[0] + c.0, cell[1] + c.1, cell[2] + c.2]) {
+                case |= 1 << i;
+            }
+        }
+        case
+    }
+
+    /// Key of the face with minimum corner `p` and normal along `axis`
+    fn face_key(&self, p: [usize; 3], axis: usize) -> usize {
+        3 * index(p[0], p[1], p[2], self.dims[0], self.dims[1]) + axis
+    }
+
+    /// Minimum corner and normal axis of face `f` of `cell`, numbered as in
+    /// [`CellShape::cube`]
+    fn cell_face(cell: [usize; 3], f: usize) -> ([usize; 3], usize) {
+        let (side, axis) = (f / 3, f % 3);
+        let mut p = cell;
+        p[axis] += side;
+        (p, axis)
+    }
+
+    /// Whether the face is shared by two cells and has two crossings of the
+    /// surface
+    fn ambiguous(&self, p: [usize; 3], axis: usize) -> bool {
+        let interior = (0..3).all(|i| {
+            if i == axis {
+                p[i] >= 1 && p[i] + 2 <= self.dims[i]
+            } else {
+                p[i] + 2 <= self.dims[i]
+            }
+        });
+        if !interior {
+            return false;
+        }
+
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let offset = |du: usize, dv: usize| {
+            let mut q = p;
+            q[u] += du;
+            q[v] += dv;
+            self.positive(q)
+        };
+        let (s00, s10, s01, s11) = (offset(0, 0), offset(1, 0), offset(0, 1), offset(1, 1));
+        s00 == s11 && s10 == s01 && s00 != s10
+    }
+
+    /// Loops of crossed edges of `cell`
+    fn loops(&self, cell: [usize; 3], case: usize) -> Cow<'static, [Vec<u8>]> {
+        let flipped = |f: usize| {
+            let (p, axis) = Self::cell_face(cell, f);
+            self.flips.contains(&self.face_key(p, axis))
+        };
+
+        if self.flips.is_empty() || !(0..6).any(flipped) {
+            Cow::Borrowed(&self.table.components[case])
+        } else {
+            Cow::Owned(self.table.shape.loops(case, flipped))
+        }
+    }
+
+    /// Whether both segments of the surface on face `f` of `cell` belong to
+    /// the same component
+    fn joins_segments(&self, cell: [usize; 3], f: usize) -> bool {
+        let (side, axis) = (f / 3, f % 3);
+        let on_face = |c: usize| [CORNERS[c].0, CORNERS[c].1, CORNERS[c].2][axis] == side;
+
+        let case = self.case(cell);
+        let map = edge_map(&self.loops(cell, case));
+        let mut components = self
+            .table
+            .shape
+            .edges()
+            .iter()
+            .enumerate()
+            .filter(|&(_, &(a, b))| on_face(a) && on_face(b))
+            .map(|(e, _)| map[e])
+            .filter(|&c| c != u8::MAX);
+
+        let first = components.next();
+        components.all(|c| Some(c) == first)
+    }
+
+    /// Connects the negative corners of every ambiguous face where both
+    /// cells would otherwise have a single component touching the face twice
+    ///
+    /// The vertices of those components would be joined by an edge shared by
+    /// four quads. After flipping, the negative corners stay connected around
+    /// the face, so the positive ones can not be, and the face is resolved for
+    /// good. Flips only ever add negative connections, so this converges.
+    fn resolve_faces(&mut self) {
+        let [width, height, depth] = self.dims;
+
+        let slabs = map_slabs(depth, |layers| {
+            let mut faces = Vec::new();
+            for z in layers {
+                for y in 0..height {
+                    for x in 0..width {
+                        for axis in 0..3 {
+                            if self.ambiguous([x, y, z], axis) {
+                                faces.push(([x, y, z], axis));
+                            }
+                        }
+                    }
+                }
+            }
+            faces
+        });
+        let mut queue = slabs.concat();
+
+        while let Some((p, axis)) = queue.pop() {
+            let key = self.face_key(p, axis);
+            if self.flips.contains(&key) {
+                continue;
+            }
+
+            let mut low = p;
+            low[axis] -= 1;
+            if self.joins_segments(low, 3 + axis) && self.joins_segments(p, axis) {
+                self.flips.insert(key);
+
+                // Other faces of both cells may now have their segments joined
+                for cell in [low, p] {
+                    for f in 0..6 {
+                        let (q, axis) = Self::cell_face(cell, f);
+                        if self.ambiguous(q, axis) {
+                            queue.push((q, axis));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Start and axis of the grid edge of `cell` between the given corners
+    fn grid_edge(cell: [usize; 3], (a, b): (usize, usize)) -> ([usize; 3], usize) {
+        let (ca, cb) = (CORNERS[a], CORNERS[b]);
+        let axis = if ca.0 != cb.0 { 0 } else if ca.1 != cb.1 { 1 } else { 2 };
+        ([cell[0] + ca.0, cell[1] + ca.1, cell[2] + ca.2], axis)
+    }
+
+    /// Vertex of the component with crossed `edges`, relative to `cell`
+    fn vertex(&self, cell: [usize; 3], edges: &[u8]) -> Vec3 {
+        let mut qef = Qef::new();
+        for &e in edges {
+            let (a, b) = self.table.shape.edges()[e as usize];
+            if let Some((p, n)) = edge_crossing(self.field, self.iso_level, cell, CORNERS[a], CORNERS[b]) {
+                qef.add(p, n);
+            }
+        }
+
+        let (vertex, _) = qef.solve_in_cell(Vec3::ZERO, Vec3::ONE, self.options);
+        vertex
+    }
+
+    /// Computes one vertex per surface component of every cell
+    ///
+    /// Returns the vertex positions, a map from cell index to its first
+    /// vertex, the case of every cell and, for the cells where it differs
+    /// from the component table, which vertex of the cell each edge uses.
+    #[allow(clippy::type_complexity)]
+    fn cell_vertices(
+        &self,
+        origin: [usize; 3],
+        scale: Vec3,
+    ) -> (Vec<Vec3>, Vec<u32>, Vec<u8>, HashMap<usize, [u8; 12]>) {
+        let [width, height, depth] = self.dims;
+
+        let slabs = map_slabs(depth - 1, |layers| {
+            let mut vertices = Vec::<Vec3>::new();
+            let mut cell_to_vertex = vec![NO_VERTEX; width * height * layers.len()];
+            let mut cell_cases = vec![0; width * height * layers.len()];
+            let mut edge_vertices = Vec::<(usize, [u8; 12])>::new();
+
+            for z in layers.clone() {
+                for y in 0..height-1 {
+                    for x in 0..width-1 {
+                        let cell = [x, y, z];
+                        let case = self.case(cell);
+                        if case == 0 || case == 255 {
+                            continue;
+                        }
+
+                        let i = index(x, y, z - layers.start, width, height);
+                        cell_to_vertex[i] = vertices.len() as u32;
+                        cell_cases[i] = case as u8;
+
+                        // Pieces of the components that are connected by quads
+                        let has_quad = |e: u8| {
+                            let (p, axis) = Self::grid_edge(cell, self.table.shape.edges()[e as usize]);
+                            has_quad(self.dims, p, axis)
+                        };
+                        let pieces: Vec<Vec<u8>> = self
+                            .loops(cell, case)
+                            .iter()
+                            .flat_map(|edges| runs(edges, has_quad))
+                            .collect();
+
+                        let map = edge_map(&pieces);
+                        if map != self.table.edge_component[case] {
+                            edge_vertices.push((index(x, y, z, width, height), map));
+                        }
+
+                        for edges in &pieces {
+                            let vertex = self.vertex(cell, edges);
+                            vertices.push((Vec3::new(
+                                (origin[0] + x) as f32,
+                                (origin[1] + y) as f32,
+                                (origin[2] + z) as f32,
+                            ) + vertex) * scale);
+                        }
+                    }
+                }
+            }
+            (vertices, cell_to_vertex, cell_cases, edge_vertices)
+        });
+
+        let mut vertices = Vec::<Vec3>::new();
+        let mut cell_to_vertex = Vec::<u32>::with_capacity(width * height * depth);
+        let mut cell_cases = Vec::<u8>::with_capacity(width * height * depth);
+        let mut edge_vertices = HashMap::new();
+        for (slab_vertices, slab_cell_to_vertex, slab_cell_cases, slab_edge_vertices) in slabs {
+            let offset = vertices.len() as u32;
+            vertices.extend(slab_vertices);
+            cell_to_vertex.extend(slab_cell_to_vertex.into_iter().map(|v| {
+                if v == NO_VERTEX { v } else { v + offset }
+            }));
+            cell_cases.extend(slab_cell_cases);
+            edge_vertices.extend(slab_edge_vertices);
+        }
+        // The last layer of samples has no cells
+        cell_to_vertex.resize(width * height * depth, NO_VERTEX);
+        cell_cases.resize(width * height * depth, 0);
+
+        (vertices, cell_to_vertex, cell_cases, edge_vertices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{edge_counts, noise, FnField};
+
+    #[test]
+    fn noise_gives_manifold_edges() {
+        for seed in 0..4 {
+            let field = FnField { dims: [12, 12, 12], f: |p| noise(p, seed) };
+            let mesh = dual_contouring_manifold(&field, 0.0, false);
+            assert!(!mesh.is_empty());
+
+            let max = edge_counts(&mesh).into_values().max().unwrap();
+            assert!(max <= 2, "seed {seed}: edge shared by {max} triangles");
+        }
+    }
+
+    #[test]
+    fn plain_dual_contouring_is_not_manifold_on_noise() {
+        let field = FnField { dims: [12, 12, 12], f: |p| noise(p, 0) };
+        let mesh = crate::dual_contouring_indexed(&field, 0.0, false);
+        assert!(edge_counts(&mesh).into_values().any(|n| n > 2));
+    }
+}
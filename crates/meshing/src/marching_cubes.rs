@@ -1,5 +1,16 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
 use glam::Vec3;
 
+use crate::degenerate::{face_normal, unit_normal};
+use crate::error::MeshingError;
+use crate::field::{gradient, index, normalize_scale, validate_field, ScalarField};
+use crate::marching_cubes_33;
+use crate::mesh::Mesh;
+use crate::options::MeshingOptions;
+use crate::parallel::{map_slabs, SLAB_DEPTH};
+
 pub const EDGE_TABLE: &[u32; 256] = &[
     0x000, 0x109, 0x203, 0x30a, 0x406, 0x50f, 0x605, 0x70c,
     0x80c, 0x905, 0xa0f, 0xb06, 0xc0a, 0xd03, 0xe09, 0xf00,
@@ -294,43 +305,383 @@ pub const TRI_TABLE: &[[i32; 16]; 256] = &[
     [-1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1]
 ];
 
-fn interp_vertex(p1: Vec3, p2: Vec3, v1: f32, v2: f32) -> Vec3 {
-    let mu = (0.0 - v1) / (v2 - v1);
+/// Point between `p1` and `p2`, with densities `v1` and `v2`, where the
+/// density crosses `iso_level`
+///
+/// Always interpolates from the lower point so that the cells sharing an edge,
+/// also in neighbouring chunks, compute the exact same position.
+pub(crate) fn interp_vertex(p1: Vec3, p2: Vec3, v1: f32, v2: f32, iso_level: f32) -> Vec3 {
+    if (p2.z, p2.y, p2.x) < (p1.z, p1.y, p1.x) {
+        return interp_vertex(p2, p1, v2, v1, iso_level);
+    }
+
+    let mu = (iso_level - v1) / (v2 - v1);
     p1 + mu * (p2 - p1)
 }
 
-fn index(x: usize, y: usize, z: usize, width: usize, height: usize) -> usize {
-    x + y * width + z * width * height
+/// Normal at the crossing a fraction `t` of the way from corner `a` to corner
+/// `b` of cell (x, y, z), interpolated between the gradients at both corners
+///
+/// The normal is that of the surface with the grid stretched by `scale`, as
+/// the gradient shrinks along the axes the grid is stretched along.
+pub(crate) fn edge_normal<F: ScalarField + ?Sized>(
+    field: &F,
+    [x, y, z]: [usize; 3],
+    a: (usize, usize, usize),
+    b: (usize, usize, usize),
+    t: f32,
+    scale: Vec3,
+) -> Vec3 {
+    let g = |c: (usize, usize, usize)| gradient(field, x + c.0, y + c.1, z + c.2);
+    unit_normal(g(a).lerp(g(b), t) / scale)
 }
 
-pub fn marching_cubes(
-    density: &[f32],
-    width: usize,
-    height: usize,
-    depth: usize,
+const CORNERS: [(usize, usize, usize); 8] = [
+    (0, 0, 1),
+    (1, 0, 1),
+    (1, 0, 0),
+    (0, 0, 0),
+    (0, 1, 1),
+    (1, 1, 1),
+    (1, 1, 0),
+    (0, 1, 0),
+];
+
+/// Corners connected by each edge, in the bit order of `EDGE_TABLE`
+const EDGE_CORNERS: [(usize, usize); 12] = [
+    (0, 1),
+    (1, 2),
+    (2, 3),
+    (3, 0),
+    (4, 5),
+    (5, 6),
+    (6, 7),
+    (7, 4),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+/// Key identifying the grid edge `edge` of cell (x, y, z), shared by all cells touching the edge
+fn edge_key(x: usize, y: usize, z: usize, edge: usize, width: usize, height: usize) -> usize {
+    let a = CORNERS[EDGE_CORNERS[edge].0];
+    let b = CORNERS[EDGE_CORNERS[edge].1];
+    let axis = if a.0 != b.0 { 0 } else if a.1 != b.1 { 1 } else { 2 };
+    3 * index(x + a.0.min(b.0), y + a.1.min(b.1), z + a.2.min(b.2), width, height) + axis
+}
+
+/// How marching cubes picks the surface in cells whose corner signs allow
+/// more than one topology
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MarchingCubesMode {
+    /// The classic 256 entry lookup table, which resolves ambiguous faces and
+    /// interiors by sign configuration alone
+    #[default]
+    Classic,
+    /// MC33 style, E V Chernyaev, Marching Cubes 33
+    ///
+    /// Ambiguous faces are resolved with the asymptotic decider on the
+    /// bilinear interpolant of the face and ambiguous interiors by testing the
+    /// trilinear interpolant for tunnels, giving the topology of the
+    /// trilinear surface. Some cells get additional vertices inside them,
+    /// where the surface can't be triangulated from the edge crossings alone
+    /// without touching a face.
+    Mc33,
+}
+
+/// How marching cubes computes the vertex normals
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NormalMode {
+    /// Normals of the triangles, flat for triangle soups and the area
+    /// weighted average of the adjacent triangles for indexed meshes
+    #[default]
+    Face,
+    /// Gradient of the field, interpolated along the edge of each crossing
+    ///
+    /// Uses [`ScalarField::gradient`], e.g. the normals given to
+    /// [`crate::DenseField::with_normals`], or central differences if the
+    /// field has none. Gives smooth shading even for triangle soups.
+    Gradient,
+}
+
+/// Extracts the isosurface where the field equals `iso_level`
+///
+/// Samples with a density above `iso_level` are considered outside, the same
+/// convention as [`crate::dual_contouring`].
+pub fn marching_cubes<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+) -> Mesh {
+    extract(field, iso_level, NormalMode::Face, [0, 0, 0], normalize_scale(field)).into()
+}
+
+/// [`marching_cubes`], failing rather than panicking or meshing NaNs if
+/// `field` is invalid, see [`validate_field`]
+pub fn try_marching_cubes<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+) -> Result<Mesh, MeshingError> {
+    validate_field(field)?;
+    Ok(marching_cubes(field, iso_level))
+}
+
+/// [`marching_cubes`] with the given options, including the handling of
+/// ambiguous cells and normals
+pub fn marching_cubes_with<F: ScalarField + ?Sized>(
+    field: &F,
+    options: MeshingOptions,
+) -> Mesh {
+    let grid = options.grid(field);
+    let (iso_level, normals, scale) = (options.iso_level, options.normals, grid.spacing);
+    let mesh = match options.mode {
+        MarchingCubesMode::Classic => extract(field, iso_level, normals, [0, 0, 0], scale),
+        MarchingCubesMode::Mc33 => marching_cubes_33::extract(field, iso_level, normals, [0, 0, 0], scale),
+    };
+    options.finish(mesh.into(), &grid)
+}
+
+/// Marching cubes with vertices at `(origin + p) * scale`, where `p` is the
+/// position in grid coordinates of `field`
+pub(crate) fn extract<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    normals: NormalMode,
+    origin: [usize; 3],
+    scale: Vec3,
 ) -> (Vec::<[f32;3]>, Vec::<[f32;3]>) {
-    let corners = [
-        (0, 0, 1),
-        (1, 0, 1),
-        (1, 0, 0),
-        (0, 0, 0),
-        (0, 1, 1),
-        (1, 1, 1),
-        (1, 1, 0),
-        (0, 1, 0),
-    ];
+    let [width, height, depth] = field.dims();
+
+    let slabs = map_slabs(depth - 1, |layers| {
+        let mut mesh_vertices = Vec::<[f32;3]>::new();
+        let mut mesh_normals = Vec::<[f32;3]>::new();
+
+        for z in layers {
+            for y in 0..height-1 {
+                for x in 0..width-1 {
+                    let mut corner_densities = [0.0_f32; 8];
+                    let mut cube_idx = 0_u32;
+                    for c in 0..8 {
+                        let corner = CORNERS[c];
+                        let d = field.sample_relative(x + corner.0, y + corner.1, z + corner.2, iso_level);
+                        corner_densities[c] = d;
+                        cube_idx |= ((d > 0.0) as u32) << c;
+                    }
+
+                    if cube_idx == 0 || cube_idx == 255 {
+                        continue;
+                    }
+
+                    let mut corner_positions = [Vec3::ZERO; 8];
+                    for c in 0..8 {
+                        let corner = CORNERS[c];
+                        corner_positions[c] = Vec3::new(
+                            (origin[0] + x + corner.0) as f32,
+                            (origin[1] + y + corner.1) as f32,
+                            (origin[2] + z + corner.2) as f32,
+                        );
+                    }
+
+                    let vertices = vec![
+                        (EDGE_TABLE[cube_idx as usize] & 1 != 0) as i32 as f32 * interp_vertex(
+                            corner_positions[0], corner_positions[1], corner_densities[0], corner_densities[1], 0.0
+                        ),
+                        (EDGE_TABLE[cube_idx as usize] & 2 != 0) as i32 as f32 * interp_vertex(
+                            corner_positions[1], corner_positions[2], corner_densities[1], corner_densities[2], 0.0
+                        ),
+                        (EDGE_TABLE[cube_idx as usize] & 4 != 0) as i32 as f32 * interp_vertex(
+                            corner_positions[2], corner_positions[3], corner_densities[2], corner_densities[3], 0.0
+                        ),
+                        (EDGE_TABLE[cube_idx as usize] & 8 != 0) as i32 as f32 * interp_vertex(
+                            corner_positions[3], corner_positions[0], corner_densities[3], corner_densities[0], 0.0
+                        ),
+                        (EDGE_TABLE[cube_idx as usize] & 16 != 0) as i32 as f32 * interp_vertex(
+                            corner_positions[4], corner_positions[5], corner_densities[4], corner_densities[5], 0.0
+                        ),
+                        (EDGE_TABLE[cube_idx as usize] & 32 != 0) as i32 as f32 * interp_vertex(
+                            corner_positions[5], corner_positions[6], corner_densities[5], corner_densities[6], 0.0
+                        ),
+                        (EDGE_TABLE[cube_idx as usize] & 64 != 0) as i32 as f32 * interp_vertex(
+                            corner_positions[6], corner_positions[7], corner_densities[6], corner_densities[7], 0.0
+                        ),
+                        (EDGE_TABLE[cube_idx as usize] & 128 != 0) as i32 as f32 * interp_vertex(
+                            corner_positions[7], corner_positions[4], corner_densities[7], corner_densities[4], 0.0
+                        ),
+                        (EDGE_TABLE[cube_idx as usize] & 256 != 0) as i32 as f32 * interp_vertex(
+                            corner_positions[0], corner_positions[4], corner_densities[0], corner_densities[4], 0.0
+                        ),
+                        (EDGE_TABLE[cube_idx as usize] & 512 != 0) as i32 as f32 * interp_vertex(
+                            corner_positions[1], corner_positions[5], corner_densities[1], corner_densities[5], 0.0
+                        ),
+                        (EDGE_TABLE[cube_idx as usize] & 1024 != 0) as i32 as f32 * interp_vertex(
+                            corner_positions[2], corner_positions[6], corner_densities[2], corner_densities[6], 0.0
+                        ),
+                        (EDGE_TABLE[cube_idx as usize] & 2048 != 0) as i32 as f32 * interp_vertex(
+                            corner_positions[3], corner_positions[7], corner_densities[3], corner_densities[7], 0.0
+                        ),
+                    ];
+
+                    let mut vertex_normals = [Vec3::ZERO; 12];
+                    if normals == NormalMode::Gradient {
+                        for (e, &(a, b)) in EDGE_CORNERS.iter().enumerate() {
+                            if EDGE_TABLE[cube_idx as usize] & (1 << e) != 0 {
+                                let t = -corner_densities[a] / (corner_densities[b] - corner_densities[a]);
+                                vertex_normals[e] = edge_normal(field, [x, y, z], CORNERS[a], CORNERS[b], t, scale);
+                            }
+                        }
+                    }
+
+                    let mut tri_idx: usize = 0;
+                    loop {
+                        let [e0, e1, e2] = [0, 1, 2].map(|i| TRI_TABLE[cube_idx as usize][tri_idx + i] as usize);
+                        let v0 = vertices[e0] * scale;
+                        let v1 = vertices[e1] * scale;
+                        let v2 = vertices[e2] * scale;
+
+                        mesh_vertices.push(v0.into());
+                        mesh_vertices.push(v1.into());
+                        mesh_vertices.push(v2.into());
+
+                        match normals {
+                            NormalMode::Face => {
+                                let normal = face_normal(v0, v1, v2);
+                                mesh_normals.push(normal.into());
+                                mesh_normals.push(normal.into());
+                                mesh_normals.push(normal.into());
+                            }
+                            NormalMode::Gradient => {
+                                mesh_normals.push(vertex_normals[e0].into());
+                                mesh_normals.push(vertex_normals[e1].into());
+                                mesh_normals.push(vertex_normals[e2].into());
+                            }
+                        }
+
+                        tri_idx += 3;
+                        if TRI_TABLE[cube_idx as usize][tri_idx] == -1 {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        (mesh_vertices, mesh_normals)
+    });
 
     let mut mesh_vertices = Vec::<[f32;3]>::new();
     let mut mesh_normals = Vec::<[f32;3]>::new();
+    for (vertices, normals) in slabs {
+        mesh_vertices.extend(vertices);
+        mesh_normals.extend(normals);
+    }
+    (mesh_vertices, mesh_normals)
+}
 
-    for z in 0..depth-1 {
+/// Marching cubes producing an indexed mesh
+///
+/// Vertices are shared between all triangles crossing the same grid edge and
+/// vertex normals are the area weighted average of the adjacent face normals.
+pub fn marching_cubes_indexed<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+) -> Mesh {
+    extract_indexed(field, iso_level, NormalMode::Face, [0, 0, 0], normalize_scale(field)).into()
+}
+
+/// [`marching_cubes_indexed`], failing if `field` is invalid, see
+/// [`try_marching_cubes`]
+pub fn try_marching_cubes_indexed<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+) -> Result<Mesh, MeshingError> {
+    validate_field(field)?;
+    Ok(marching_cubes_indexed(field, iso_level))
+}
+
+/// [`marching_cubes_indexed`] with the given options, see
+/// [`marching_cubes_with`]
+pub fn marching_cubes_indexed_with<F: ScalarField + ?Sized>(
+    field: &F,
+    options: MeshingOptions,
+) -> Mesh {
+    let grid = options.grid(field);
+    let (iso_level, normals, scale) = (options.iso_level, options.normals, grid.spacing);
+    let mesh = match options.mode {
+        MarchingCubesMode::Classic => extract_indexed(field, iso_level, normals, [0, 0, 0], scale),
+        MarchingCubesMode::Mc33 => marching_cubes_33::extract_indexed(field, iso_level, normals, [0, 0, 0], scale),
+    };
+    options.finish(mesh.into(), &grid)
+}
+
+/// Indexed version of [`extract`]
+pub(crate) fn extract_indexed<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    normals: NormalMode,
+    origin: [usize; 3],
+    scale: Vec3,
+) -> (Vec<[f32;3]>, Vec<[f32;3]>, Vec<u32>) {
+    let [width, height, depth] = field.dims();
+
+    let slabs = map_slabs(depth - 1, |layers| {
+        let mut buffers = IndexedBuffers::default();
+        extract_layers(field, iso_level, normals, origin, scale, layers, &mut buffers);
+        (buffers.positions, buffers.normals, buffers.keys, buffers.indices)
+    });
+
+    merge_slabs(slabs, depth, |key| key / 3 / (width * height))
+}
+
+/// Buffers the cell layers of an indexed mesh are extracted into, see
+/// [`IndexedSlab`]
+#[derive(Default)]
+pub(crate) struct IndexedBuffers {
+    pub(crate) positions: Vec<[f32;3]>,
+    pub(crate) normals: Vec<Vec3>,
+    pub(crate) keys: Vec<usize>,
+    pub(crate) indices: Vec<u32>,
+    /// Grid edge -> vertex index
+    pub(crate) edge_vertices: HashMap<usize, u32>,
+}
+
+impl IndexedBuffers {
+    /// Empties the buffers, keeping their allocations
+    pub(crate) fn clear(&mut self) {
+        self.positions.clear();
+        self.normals.clear();
+        self.keys.clear();
+        self.indices.clear();
+        self.edge_vertices.clear();
+    }
+}
+
+/// Appends the vertices and triangles of the cells in `layers` along z to
+/// `buffers`, see [`extract_indexed`]
+pub(crate) fn extract_layers<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    normals: NormalMode,
+    origin: [usize; 3],
+    scale: Vec3,
+    layers: Range<usize>,
+    buffers: &mut IndexedBuffers,
+) {
+    let [width, height, _] = field.dims();
+    let IndexedBuffers {
+        positions: mesh_vertices,
+        normals: vertex_normals,
+        keys: vertex_keys,
+        indices: mesh_indices,
+        edge_vertices,
+    } = buffers;
+
+    for z in layers {
         for y in 0..height-1 {
             for x in 0..width-1 {
                 let mut corner_densities = [0.0_f32; 8];
                 let mut cube_idx = 0_u32;
                 for c in 0..8 {
-                    let corner = corners[c];
-                    let d = density[index(x + corner.0, y + corner.1, z + corner.2, width, height)];
+                    let corner = CORNERS[c];
+                    let d = field.sample_relative(x + corner.0, y + corner.1, z + corner.2, iso_level);
                     corner_densities[c] = d;
                     cube_idx |= ((d > 0.0) as u32) << c;
                 }
@@ -339,82 +690,144 @@ pub fn marching_cubes(
                     continue;
                 }
 
-                let mut corner_positions = [Vec3::ZERO; 8];
-                for c in 0..8 {
-                    let corner = corners[c];
-                    corner_positions[c] = Vec3::new(
-                        (x + corner.0) as f32,
-                        (y + corner.1) as f32,
-                        (z + corner.2) as f32,
-                    );
+                let mut vertices = [0_u32; 12];
+                for (e, &(a, b)) in EDGE_CORNERS.iter().enumerate() {
+                    if EDGE_TABLE[cube_idx as usize] & (1 << e) == 0 {
+                        continue;
+                    }
+
+                    let key = edge_key(x, y, z, e, width, height);
+                    vertices[e] = *edge_vertices
+                        .entry(key)
+                        .or_insert_with(|| {
+                            let p = |c: usize| Vec3::new(
+                                (origin[0] + x + CORNERS[c].0) as f32,
+                                (origin[1] + y + CORNERS[c].1) as f32,
+                                (origin[2] + z + CORNERS[c].2) as f32,
+                            );
+                            let v = interp_vertex(p(a), p(b), corner_densities[a], corner_densities[b], 0.0);
+                            let normal = match normals {
+                                NormalMode::Face => Vec3::ZERO,
+                                NormalMode::Gradient => {
+                                    let t = -corner_densities[a] / (corner_densities[b] - corner_densities[a]);
+                                    edge_normal(field, [x, y, z], CORNERS[a], CORNERS[b], t, scale)
+                                }
+                            };
+
+                            mesh_vertices.push((v * scale).into());
+                            vertex_normals.push(normal);
+                            vertex_keys.push(key);
+                            (mesh_vertices.len() - 1) as u32
+                        });
                 }
 
-                let vertices = vec![
-                    (EDGE_TABLE[cube_idx as usize] & 1 != 0) as i32 as f32 * interp_vertex(
-                        corner_positions[0], corner_positions[1], corner_densities[0], corner_densities[1]
-                    ),
-                    (EDGE_TABLE[cube_idx as usize] & 2 != 0) as i32 as f32 * interp_vertex(
-                        corner_positions[1], corner_positions[2], corner_densities[1], corner_densities[2]
-                    ),
-                    (EDGE_TABLE[cube_idx as usize] & 4 != 0) as i32 as f32 * interp_vertex(
-                        corner_positions[2], corner_positions[3], corner_densities[2], corner_densities[3]
-                    ),
-                    (EDGE_TABLE[cube_idx as usize] & 8 != 0) as i32 as f32 * interp_vertex(
-                        corner_positions[3], corner_positions[0], corner_densities[3], corner_densities[0]
-                    ),
-                    (EDGE_TABLE[cube_idx as usize] & 16 != 0) as i32 as f32 * interp_vertex(
-                        corner_positions[4], corner_positions[5], corner_densities[4], corner_densities[5]
-                    ),
-                    (EDGE_TABLE[cube_idx as usize] & 32 != 0) as i32 as f32 * interp_vertex(
-                        corner_positions[5], corner_positions[6], corner_densities[5], corner_densities[6]
-                    ),
-                    (EDGE_TABLE[cube_idx as usize] & 64 != 0) as i32 as f32 * interp_vertex(
-                        corner_positions[6], corner_positions[7], corner_densities[6], corner_densities[7]
-                    ),
-                    (EDGE_TABLE[cube_idx as usize] & 128 != 0) as i32 as f32 * interp_vertex(
-                        corner_positions[7], corner_positions[4], corner_densities[7], corner_densities[4]
-                    ),
-                    (EDGE_TABLE[cube_idx as usize] & 256 != 0) as i32 as f32 * interp_vertex(
-                        corner_positions[0], corner_positions[4], corner_densities[0], corner_densities[4]
-                    ),
-                    (EDGE_TABLE[cube_idx as usize] & 512 != 0) as i32 as f32 * interp_vertex(
-                        corner_positions[1], corner_positions[5], corner_densities[1], corner_densities[5]
-                    ),
-                    (EDGE_TABLE[cube_idx as usize] & 1024 != 0) as i32 as f32 * interp_vertex(
-                        corner_positions[2], corner_positions[6], corner_densities[2], corner_densities[6]
-                    ),
-                    (EDGE_TABLE[cube_idx as usize] & 2048 != 0) as i32 as f32 * interp_vertex(
-                        corner_positions[3], corner_positions[7], corner_densities[3], corner_densities[7]
-                    ),
-                ];
-
-                let mut tri_idx: usize = 0;
-                let scale = Vec3::new(
-                    1.0 / width as f32,
-                    1.0 / height as f32,
-                    1.0 / depth as f32
-                );
-                loop {
-                    let v0 = vertices[TRI_TABLE[cube_idx as usize][tri_idx] as usize] * scale;
-                    let v1 = vertices[TRI_TABLE[cube_idx as usize][tri_idx + 1] as usize] * scale;
-                    let v2 = vertices[TRI_TABLE[cube_idx as usize][tri_idx + 2] as usize] * scale;
-
-                    mesh_vertices.push(v0.into());
-                    mesh_vertices.push(v1.into());
-                    mesh_vertices.push(v2.into());
-
-                    let normal = (v0 - v1).cross(v0 - v2).normalize();
-                    mesh_normals.push(normal.into());
-                    mesh_normals.push(normal.into());
-                    mesh_normals.push(normal.into());
-
-                    tri_idx += 3;
-                    if TRI_TABLE[cube_idx as usize][tri_idx] == -1 {
+                for tri in TRI_TABLE[cube_idx as usize].chunks(3) {
+                    if tri[0] == -1 {
                         break;
                     }
+
+                    let i0 = vertices[tri[0] as usize];
+                    let i1 = vertices[tri[1] as usize];
+                    let i2 = vertices[tri[2] as usize];
+
+                    let v0 = Vec3::from(mesh_vertices[i0 as usize]);
+                    let v1 = Vec3::from(mesh_vertices[i1 as usize]);
+                    let v2 = Vec3::from(mesh_vertices[i2 as usize]);
+
+                    if normals == NormalMode::Face {
+                        // Not normalized, larger triangles get a larger weight
+                        let normal = (v0 - v1).cross(v0 - v2);
+                        vertex_normals[i0 as usize] += normal;
+                        vertex_normals[i1 as usize] += normal;
+                        vertex_normals[i2 as usize] += normal;
+                    }
+
+                    mesh_indices.push(i0);
+                    mesh_indices.push(i1);
+                    mesh_indices.push(i2);
+                }
+            }
+        }
+    }
+}
+
+/// Positions, unnormalized normals, grid edge keys and indices of the
+/// vertices of one slab of an indexed mesh
+pub(crate) type IndexedSlab = (Vec<[f32;3]>, Vec<Vec3>, Vec<usize>, Vec<u32>);
+
+/// Joins the slabs of an indexed mesh, merging the vertices on the grid
+/// planes shared by consecutive slabs
+///
+/// `plane` returns the z coordinate of the grid edge identified by a key.
+pub(crate) fn merge_slabs(
+    slabs: Vec<IndexedSlab>,
+    depth: usize,
+    plane: impl Fn(usize) -> usize,
+) -> (Vec<[f32;3]>, Vec<[f32;3]>, Vec<u32>) {
+    let mut mesh_vertices = Vec::<[f32;3]>::new();
+    let mut vertex_normals = Vec::<Vec3>::new();
+    let mut mesh_indices = Vec::<u32>::new();
+
+    // Vertices on the top plane of the previous slab, shared with the current one
+    let mut shared_vertices = HashMap::<usize, u32>::new();
+    for (slab, (vertices, normals, keys, indices)) in slabs.into_iter().enumerate() {
+        let top_plane = ((slab + 1) * SLAB_DEPTH).min(depth - 1);
+
+        let mut top_vertices = HashMap::<usize, u32>::new();
+        let remap: Vec<u32> = vertices.iter().zip(&normals).zip(&keys)
+            .map(|((&position, &normal), &key)| {
+                let index = if let Some(&index) = shared_vertices.get(&key) {
+                    vertex_normals[index as usize] += normal;
+                    index
+                } else {
+                    mesh_vertices.push(position);
+                    vertex_normals.push(normal);
+                    (mesh_vertices.len() - 1) as u32
+                };
+
+                if plane(key) == top_plane {
+                    top_vertices.insert(key, index);
+                }
+                index
+            })
+            .collect();
+
+        mesh_indices.extend(indices.iter().map(|&i| remap[i as usize]));
+        shared_vertices = top_vertices;
+    }
+
+    let mesh_normals = vertex_normals
+        .into_iter()
+        .map(|n| unit_normal(n).into())
+        .collect();
+
+    (mesh_vertices, mesh_normals, mesh_indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+    use crate::geometry::GridGeometry;
+    use crate::test_util::sphere;
+
+    #[test]
+    fn gradient_normals_point_outwards() {
+        let center = Vec3::new(7.3, 7.6, 7.9);
+        let field = sphere([16; 3], center, 5.3);
+        let options = MeshingOptions::new()
+            .normals(NormalMode::Gradient)
+            .geometry(GridGeometry::default());
+        for mode in [MarchingCubesMode::Classic, MarchingCubesMode::Mc33] {
+            let options = options.mode(mode);
+            for mesh in [marching_cubes_with(&field, options), marching_cubes_indexed_with(&field, options)] {
+                assert!(!mesh.is_empty());
+                for (&p, &n) in mesh.positions.iter().zip(&mesh.normals) {
+                    let radial = (Vec3::from(p) - center).normalize();
+                    assert!(Vec3::from(n).dot(radial) > 0.99, "{p:?}: {n:?}");
                 }
             }
         }
     }
-    (mesh_vertices, mesh_normals)
 }
@@ -0,0 +1,854 @@
+//! Marching cubes 33, E V Chernyaev, Marching Cubes 33
+//!
+//! The published case and subcase tables are not used. Instead the loops of
+//! crossed edges for each sign configuration and resolution of the ambiguous
+//! faces are traced with [`CellShape::loops`] when the table is first used,
+//! and loops joined through the interior of the cell get a tunnel between
+//! them at meshing time. This gives the topology of the published subcases,
+//! see the `cases_split_into_mc33_subcases` test, but not their triangles,
+//! e.g. some loops are fanned around a vertex inside the cell.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use glam::Vec3;
+
+use crate::cell_contour::CellShape;
+use crate::degenerate::{face_normal, unit_normal};
+use crate::field::{index, ScalarField};
+use crate::marching_cubes::{edge_normal, interp_vertex, merge_slabs, IndexedBuffers, NormalMode};
+use crate::parallel::map_slabs;
+
+/// Corner offsets, x varying fastest so corners along an axis differ in one bit
+pub(crate) const CORNERS: [(usize, usize, usize); 8] = [
+    (0, 0, 0),
+    (1, 0, 0),
+    (0, 1, 0),
+    (1, 1, 0),
+    (0, 0, 1),
+    (1, 0, 1),
+    (0, 1, 1),
+    (1, 1, 1),
+];
+
+/// Corners of face `3 * side + axis` in cyclic order, the face numbering of
+/// [`CellShape::cube`]
+pub(crate) fn face_corners(face: usize) -> [usize; 4] {
+    let (side, axis) = (face / 3, face % 3);
+    let (u, v) = (1 << ((axis + 1) % 3), 1 << ((axis + 2) % 3));
+    let base = side << axis;
+    [base, base | u, base | u | v, base | v]
+}
+
+pub(crate) fn positive(case: usize, corner: usize) -> bool {
+    case & (1 << corner) != 0
+}
+
+/// Whether the face with corners `c` in cyclic order has four crossings
+pub(crate) fn ambiguous(case: usize, c: [usize; 4]) -> bool {
+    let s = c.map(|c| positive(case, c));
+    s[0] == s[2] && s[1] == s[3] && s[0] != s[1]
+}
+
+/// Asymptotic decider, G M Nielson and B Hamann, The Asymptotic Decider
+///
+/// Decides whether the negative corners of an ambiguous face are connected,
+/// given the values relative to the iso level in cyclic order. The saddle of
+/// the bilinear interpolant has the value (ac - bd) / (a + c - b - d), where
+/// the denominator takes the sign of the diagonal a, c.
+fn connect_negative([a, b, c, d]: [f32; 4]) -> bool {
+    if a > 0.0 {
+        a * c - b * d <= 0.0
+    } else {
+        b * d - a * c <= 0.0
+    }
+}
+
+fn find(parent: &mut [usize; 8], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+fn union(parent: &mut [usize; 8], a: usize, b: usize) {
+    let (a, b) = (find(parent, a), find(parent, b));
+    parent[a.max(b)] = a.min(b);
+}
+
+/// Groups the corners into regions of equal sign connected on the boundary
+/// of the cell, bit `f` of `faces` is set if the negative corners of face
+/// `f` are connected
+fn boundary_regions(case: usize, faces: usize) -> [usize; 8] {
+    let mut parent = [0, 1, 2, 3, 4, 5, 6, 7];
+    for a in 0..8 {
+        for axis_bit in [1, 2, 4] {
+            let b = a | axis_bit;
+            if b != a && positive(case, a) == positive(case, b) {
+                union(&mut parent, a, b);
+            }
+        }
+    }
+    for f in 0..6 {
+        let c = face_corners(f);
+        if ambiguous(case, c) {
+            let connect_first = positive(case, c[0]) != (faces & (1 << f) != 0);
+            if connect_first {
+                union(&mut parent, c[0], c[2]);
+            } else {
+                union(&mut parent, c[1], c[3]);
+            }
+        }
+    }
+    parent
+}
+
+/// Whether cell edges `e0` and `e1` lie on a common face
+fn same_face(edges: &[(usize, usize)], e0: u8, e1: u8) -> bool {
+    let ((a, b), (c, d)) = (edges[e0 as usize], edges[e1 as usize]);
+    (0..3).any(|axis| [b, c, d].iter().all(|&corner| (corner ^ a) & (1 << axis) == 0))
+}
+
+/// Triangles of a cell, vertices below 12 are the crossings on the cell edges
+/// and vertex `12 + i` lies inside the cell at the average of the crossings
+/// in `centers[i]`
+#[derive(Clone, Default)]
+pub(crate) struct Triangulation {
+    pub triangles: Vec<[u8; 3]>,
+    pub centers: Vec<Vec<u8>>,
+}
+
+impl Triangulation {
+    /// Joins two loops returned by [`CellShape::loops`] with a tube
+    ///
+    /// The first loop is walked backwards and the second forwards so both
+    /// keep the boundary orientation of [`Triangulation::close`]. Edges across
+    /// the tube may not join two crossings on the same face, as the
+    /// neighbouring cell may join them as well. If that leaves no way to
+    /// join the loops directly, the tube passes through a ring of vertices
+    /// inside the cell, one between each crossing of the first loop and the
+    /// nearest crossing of the second.
+    fn tube(&mut self, edges: &[(usize, usize)], first: &[u8], second: &[u8], position: impl Fn(u8) -> Vec3) {
+        let first: Vec<u8> = first.iter().rev().copied().collect();
+        let crossing_rung = |a: u8, b: u8| {
+            if same_face(edges, a, b) {
+                f32::INFINITY
+            } else {
+                position(a).distance(position(b))
+            }
+        };
+        if strip(&first, second, crossing_rung, &mut self.triangles) {
+            return;
+        }
+
+        let nearest = |a: u8| {
+            let distance = |b: u8| (same_face(edges, a, b), position(a).distance_squared(position(b)));
+            *second.iter().min_by(|&&b, &&c| distance(b).partial_cmp(&distance(c)).unwrap()).unwrap()
+        };
+        let ring: Vec<u8> = first
+            .iter()
+            .map(|&a| {
+                self.centers.push(vec![a, nearest(a)]);
+                (11 + self.centers.len()) as u8
+            })
+            .collect();
+
+        let ring_position = |v: u8| match v {
+            0..=11 => position(v),
+            _ => self.centers[v as usize - 12].iter().map(|&e| position(e)).sum::<Vec3>() * 0.5,
+        };
+        let ring_rung = |a: u8, b: u8| ring_position(a).distance(ring_position(b));
+        let mut triangles = Vec::new();
+        strip(&first, &ring, ring_rung, &mut triangles);
+        strip(&ring, second, ring_rung, &mut triangles);
+        self.triangles.extend(triangles);
+    }
+
+    /// Closes a loop returned by [`CellShape::loops`] with a fan
+    ///
+    /// The fan starts at the first crossing that shares no face with the
+    /// crossings it is joined to, as the neighbouring cell may join them as
+    /// well. Loops without such a crossing are fanned around a vertex inside
+    /// the cell.
+    pub fn close(&mut self, edges: &[(usize, usize)], crossings: &[u8]) {
+        let n = crossings.len();
+        let at = |i: usize| crossings[i % n];
+        let start = (0..n).find(|&s| (2..n - 1).all(|k| !same_face(edges, at(s), at(s + k))));
+
+        if let Some(s) = start {
+            for i in 1..n - 1 {
+                self.triangles.push([at(s), at(s + i + 1), at(s + i)]);
+            }
+        } else {
+            let center = (12 + self.centers.len()) as u8;
+            self.centers.push(crossings.to_vec());
+            for i in 0..n {
+                self.triangles.push([center, at(i + 1), at(i)]);
+            }
+        }
+    }
+}
+
+pub(crate) struct Table {
+    pub shape: CellShape,
+    /// Triangulation for each sign configuration and face resolution, indexed
+    /// by `64 * case + faces`
+    pub triangulations: Vec<Triangulation>,
+    /// Whether some corners of equal sign are not connected on the boundary,
+    /// in which case they may be connected through the inside of the cell
+    separated: Vec<bool>,
+}
+
+pub(crate) fn table() -> &'static Table {
+    static TABLE: OnceLock<Table> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let shape = CellShape::cube(&CORNERS);
+        let mut triangulations = Vec::with_capacity(256 * 64);
+        let mut separated = Vec::with_capacity(256 * 64);
+        for case in 0..256 {
+            for faces in 0..64 {
+                let mut triangulation = Triangulation::default();
+                for crossings in shape.loops(case, |f| faces & (1 << f) != 0) {
+                    triangulation.close(shape.edges(), &crossings);
+                }
+                triangulations.push(triangulation);
+
+                let mut parent = boundary_regions(case, faces);
+                let mut regions = [false; 2];
+                let mut split = false;
+                for c in 0..8 {
+                    if find(&mut parent, c) == c {
+                        split |= regions[positive(case, c) as usize];
+                        regions[positive(case, c) as usize] = true;
+                    }
+                }
+                separated.push(split);
+            }
+        }
+        Table {
+            shape,
+            triangulations,
+            separated,
+        }
+    })
+}
+
+/// Joins regions connected through the inside of the cell
+///
+/// The slices of the trilinear interpolant perpendicular to an axis are
+/// bilinear, and two regions are connected if some slice connects them. The
+/// slice is split at the crossings along its corners, within each part the
+/// corners keep their signs and the saddle numerator is a quadratic in the
+/// slice position whose extremes lie at the ends or at its vertex.
+fn interior_links(values: &[f32; 8], parent: &mut [usize; 8]) {
+    for axis in 0..3 {
+        let axis_bit = 1 << axis;
+        let ring = face_corners(axis);
+        let start = ring.map(|c| values[c]);
+        let slope = ring.map(|c| values[c | axis_bit] - values[c]);
+        let value = |i: usize, t: f32| start[i] + t * slope[i];
+
+        let mut breaks = vec![0.0, 1.0];
+        for i in 0..4 {
+            if (start[i] > 0.0) != (value(i, 1.0) > 0.0) {
+                breaks.push(-start[i] / slope[i]);
+            }
+        }
+        breaks.sort_by(|a, b| a.total_cmp(b));
+
+        for window in breaks.windows(2) {
+            let (lo, hi) = (window[0], window[1]);
+            let mid = 0.5 * (lo + hi);
+            let s = [0, 1, 2, 3].map(|i| value(i, mid) > 0.0);
+            if !(s[0] == s[2] && s[1] == s[3] && s[0] != s[1]) {
+                continue;
+            }
+
+            // Saddle numerator with the positive diagonal first
+            let ([p0, p1], [n0, n1]) = if s[0] { ([0, 2], [1, 3]) } else { ([1, 3], [0, 2]) };
+            let numerator = |t: f32| value(p0, t) * value(p1, t) - value(n0, t) * value(n1, t);
+
+            let mut candidates = vec![numerator(lo), numerator(hi)];
+            let a = slope[p0] * slope[p1] - slope[n0] * slope[n1];
+            let b = start[p0] * slope[p1] + start[p1] * slope[p0] - start[n0] * slope[n1] - start[n1] * slope[n0];
+            if a != 0.0 {
+                let vertex = -b / (2.0 * a);
+                if lo < vertex && vertex < hi {
+                    candidates.push(numerator(vertex));
+                }
+            }
+
+            // Corner of the region a slice corner belongs to, the crossings
+            // along the edges are outside the window
+            let region = |i: usize| {
+                let c = ring[i];
+                if (values[c] > 0.0) == s[i] { c } else { c | axis_bit }
+            };
+            if candidates.iter().any(|&q| q > 0.0) {
+                union(parent, region(p0), region(p1));
+            }
+            if candidates.iter().any(|&q| q < 0.0) {
+                union(parent, region(n0), region(n1));
+            }
+        }
+    }
+}
+
+/// Triangulates the strip between two cycles of vertices
+///
+/// Each triangle advances along one of the cycles, in the order that
+/// minimizes the total length of the edges across the strip as given by
+/// `rung`, which is infinite for edges that may not be used. The strip starts
+/// by advancing along `p` and ends by advancing along `q`, which keeps it
+/// from wrapping around either cycle more than once. Returns false if every
+/// strip uses an infinite edge.
+fn strip(p: &[u8], q: &[u8], rung: impl Fn(u8, u8) -> f32, triangles: &mut Vec<[u8; 3]>) -> bool {
+    let (n, m) = (p.len(), q.len());
+    let p_at = |i: usize| p[i % n];
+
+    let mut best: Option<(f32, usize, Vec<bool>)> = None;
+    for offset in 0..m {
+        let q_at = |j: usize| q[(offset + j) % m];
+
+        // Lowest cost to reach (i, j), i steps along p and j along q, and
+        // whether the last step was along p
+        let mut cost = vec![vec![(f32::INFINITY, true); m]; n + 1];
+        cost[1][0] = (rung(p_at(0), q_at(0)) + rung(p_at(1), q_at(0)), true);
+        for i in 1..=n {
+            for j in 0..m {
+                if (i, j) == (1, 0) || (i, j) == (n, 0) {
+                    continue;
+                }
+                let from_p = if i > 1 { cost[i - 1][j].0 } else { f32::INFINITY };
+                let from_q = if j > 0 { cost[i][j - 1].0 } else { f32::INFINITY };
+                cost[i][j] = (rung(p_at(i), q_at(j)) + from_p.min(from_q), from_p <= from_q);
+            }
+        }
+
+        let total = cost[n][m - 1].0;
+        if total.is_finite() && best.as_ref().is_none_or(|b| total < b.0) {
+            let mut steps = vec![false];
+            let (mut i, mut j) = (n, m - 1);
+            while (i, j) != (1, 0) {
+                steps.push(cost[i][j].1);
+                if cost[i][j].1 { i -= 1 } else { j -= 1 }
+            }
+            steps.push(true);
+            steps.reverse();
+            best = Some((total, offset, steps));
+        }
+    }
+
+    let Some((_, offset, steps)) = best else { return false };
+    let q_at = |j: usize| q[(offset + j) % m];
+    let (mut i, mut j) = (0, 0);
+    for along_p in steps {
+        if along_p {
+            triangles.push([p_at(i), p_at(i + 1), q_at(j)]);
+            i += 1;
+        } else {
+            triangles.push([q_at(j + 1), q_at(j), p_at(i)]);
+            j += 1;
+        }
+    }
+    true
+}
+
+/// Resolves the ambiguous faces of a cell with the asymptotic decider, bit
+/// `f` of the result is set if the negative corners of face `f` are connected
+pub(crate) fn face_resolution(values: &[f32; 8], case: usize) -> usize {
+    let mut faces = 0;
+    for f in 0..6 {
+        let c = face_corners(f);
+        if ambiguous(case, c) && connect_negative(c.map(|c| values[c])) {
+            faces |= 1 << f;
+        }
+    }
+    faces
+}
+
+/// Triangulation of a cell, with `values` the corner samples relative to the
+/// iso level
+///
+/// Faces are resolved with the asymptotic decider. When corners of equal sign
+/// are connected through the inside, the two loops around them are joined by
+/// a tunnel instead of being closed separately. `position` gives the crossing
+/// on an edge and is only used to triangulate tunnels.
+fn cell_triangles<'a>(
+    table: &'a Table,
+    values: &[f32; 8],
+    case: usize,
+    position: impl Fn(u8) -> Vec3,
+) -> Cow<'a, Triangulation> {
+    let faces = face_resolution(values, case);
+    let key = 64 * case + faces;
+    if !table.separated[key] {
+        return Cow::Borrowed(&table.triangulations[key]);
+    }
+
+    let mut parent = boundary_regions(case, faces);
+    interior_links(values, &mut parent);
+
+    // Each loop separates one negative and one positive region, loops between
+    // the same pair of regions bound a tunnel
+    let loops = table.shape.loops(case, |f| faces & (1 << f) != 0);
+    let mut groups = HashMap::<(usize, usize), Vec<usize>>::new();
+    for (l, edges) in loops.iter().enumerate() {
+        let (a, b) = table.shape.edges()[edges[0] as usize];
+        let (negative, positive) = if positive(case, a) { (b, a) } else { (a, b) };
+        groups
+            .entry((find(&mut parent, negative), find(&mut parent, positive)))
+            .or_default()
+            .push(l);
+    }
+
+    if groups.values().all(|group| group.len() == 1) {
+        return Cow::Borrowed(&table.triangulations[key]);
+    }
+
+    let mut triangulation = Triangulation::default();
+    for (l, edges) in loops.iter().enumerate() {
+        let (a, b) = table.shape.edges()[edges[0] as usize];
+        let (negative, positive) = if positive(case, a) { (b, a) } else { (a, b) };
+        match groups[&(find(&mut parent, negative), find(&mut parent, positive))][..] {
+            [first, second] if first == l => {
+                triangulation.tube(table.shape.edges(), &loops[first], &loops[second], &position);
+            }
+            [_, _] => {}
+            _ => triangulation.close(table.shape.edges(), edges),
+        }
+    }
+    Cow::Owned(triangulation)
+}
+
+/// Samples of the corners of cell (x, y, z) relative to `iso_level`, and the
+/// bit set of positive corners
+pub(crate) fn cell_values<F: ScalarField + ?Sized>(field: &F, iso_level: f32, x: usize, y: usize, z: usize) -> ([f32; 8], usize) {
+    let mut values = [0.0_f32; 8];
+    let mut case = 0;
+    for (c, value) in values.iter_mut().enumerate() {
+        let (cx, cy, cz) = CORNERS[c];
+        *value = field.sample_relative(x + cx, y + cy, z + cz, iso_level);
+        case |= ((*value > 0.0) as usize) << c;
+    }
+    (values, case)
+}
+
+/// Crossings on the edges of cell (x, y, z) in grid coordinates offset by `origin`
+pub(crate) fn cell_crossings(table: &Table, values: &[f32; 8], case: usize, origin: [usize; 3], x: usize, y: usize, z: usize) -> [Vec3; 12] {
+    let p = |c: usize| Vec3::new(
+        (origin[0] + x + CORNERS[c].0) as f32,
+        (origin[1] + y + CORNERS[c].1) as f32,
+        (origin[2] + z + CORNERS[c].2) as f32,
+    );
+
+    let mut crossings = [Vec3::ZERO; 12];
+    for (e, &(a, b)) in table.shape.edges().iter().enumerate() {
+        if positive(case, a) != positive(case, b) {
+            crossings[e] = interp_vertex(p(a), p(b), values[a], values[b], 0.0);
+        }
+    }
+    crossings
+}
+
+/// Normals at the crossings on the edges of cell (x, y, z), see
+/// [`edge_normal`]
+pub(crate) fn cell_normals<F: ScalarField + ?Sized>(
+    field: &F,
+    table: &Table,
+    values: &[f32; 8],
+    case: usize,
+    [x, y, z]: [usize; 3],
+    scale: Vec3,
+) -> [Vec3; 12] {
+    let mut normals = [Vec3::ZERO; 12];
+    for (e, &(a, b)) in table.shape.edges().iter().enumerate() {
+        if positive(case, a) != positive(case, b) {
+            let t = values[a] / (values[a] - values[b]);
+            normals[e] = edge_normal(field, [x, y, z], CORNERS[a], CORNERS[b], t, scale);
+        }
+    }
+    normals
+}
+
+/// Key of vertices inside a cell, which are never shared with other cells
+const INSIDE_KEY: usize = usize::MAX;
+
+/// Average of the crossings on `edges`
+pub(crate) fn center(crossings: &[Vec3; 12], edges: &[u8]) -> Vec3 {
+    edges.iter().map(|&e| crossings[e as usize]).sum::<Vec3>() / edges.len() as f32
+}
+
+/// Marching cubes resolving ambiguous faces with the asymptotic decider and
+/// ambiguous interiors by testing for tunnels, see [`crate::marching_cubes::extract`]
+pub(crate) fn extract<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    normals: NormalMode,
+    origin: [usize; 3],
+    scale: Vec3,
+) -> (Vec<[f32;3]>, Vec<[f32;3]>) {
+    let [width, height, depth] = field.dims();
+    let table = table();
+
+    let slabs = map_slabs(depth - 1, |layers| {
+        let mut mesh_vertices = Vec::<[f32;3]>::new();
+        let mut mesh_normals = Vec::<[f32;3]>::new();
+
+        for z in layers {
+            for y in 0..height-1 {
+                for x in 0..width-1 {
+                    let (values, case) = cell_values(field, iso_level, x, y, z);
+                    if case == 0 || case == 255 {
+                        continue;
+                    }
+
+                    let crossings = cell_crossings(table, &values, case, origin, x, y, z);
+                    let triangulation = cell_triangles(table, &values, case, |e| crossings[e as usize]);
+                    let vertex = |v: u8| match v {
+                        0..=11 => crossings[v as usize] * scale,
+                        _ => center(&crossings, &triangulation.centers[v as usize - 12]) * scale,
+                    };
+
+                    let crossing_normals = match normals {
+                        NormalMode::Face => [Vec3::ZERO; 12],
+                        NormalMode::Gradient => cell_normals(field, table, &values, case, [x, y, z], scale),
+                    };
+                    let vertex_normal = |v: u8| match v {
+                        0..=11 => crossing_normals[v as usize],
+                        _ => unit_normal(center(&crossing_normals, &triangulation.centers[v as usize - 12])),
+                    };
+
+                    for &[i0, i1, i2] in &triangulation.triangles {
+                        let (v0, v1, v2) = (vertex(i0), vertex(i1), vertex(i2));
+
+                        mesh_vertices.push(v0.into());
+                        mesh_vertices.push(v1.into());
+                        mesh_vertices.push(v2.into());
+
+                        match normals {
+                            NormalMode::Face => {
+                                let normal = face_normal(v0, v1, v2);
+                                mesh_normals.push(normal.into());
+                                mesh_normals.push(normal.into());
+                                mesh_normals.push(normal.into());
+                            }
+                            NormalMode::Gradient => {
+                                mesh_normals.push(vertex_normal(i0).into());
+                                mesh_normals.push(vertex_normal(i1).into());
+                                mesh_normals.push(vertex_normal(i2).into());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        (mesh_vertices, mesh_normals)
+    });
+
+    let mut mesh_vertices = Vec::<[f32;3]>::new();
+    let mut mesh_normals = Vec::<[f32;3]>::new();
+    for (vertices, normals) in slabs {
+        mesh_vertices.extend(vertices);
+        mesh_normals.extend(normals);
+    }
+    (mesh_vertices, mesh_normals)
+}
+
+/// Indexed version of [`extract`]
+pub(crate) fn extract_indexed<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    normals: NormalMode,
+    origin: [usize; 3],
+    scale: Vec3,
+) -> (Vec<[f32;3]>, Vec<[f32;3]>, Vec<u32>) {
+    let [width, height, depth] = field.dims();
+
+    let slabs = map_slabs(depth - 1, |layers| {
+        let mut buffers = IndexedBuffers::default();
+        extract_layers(field, iso_level, normals, origin, scale, layers, &mut buffers);
+        (buffers.positions, buffers.normals, buffers.keys, buffers.indices)
+    });
+
+    merge_slabs(slabs, depth, |key| key / 3 / (width * height))
+}
+
+/// Appends the vertices and triangles of the cells in `layers` along z to
+/// `buffers`, see [`crate::marching_cubes::extract_layers`]
+pub(crate) fn extract_layers<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    normals: NormalMode,
+    origin: [usize; 3],
+    scale: Vec3,
+    layers: Range<usize>,
+    buffers: &mut IndexedBuffers,
+) {
+    let [width, height, _] = field.dims();
+    let table = table();
+    let IndexedBuffers {
+        positions: mesh_vertices,
+        normals: vertex_normals,
+        keys: vertex_keys,
+        indices: mesh_indices,
+        edge_vertices,
+    } = buffers;
+
+    for z in layers {
+        for y in 0..height-1 {
+            for x in 0..width-1 {
+                let (values, case) = cell_values(field, iso_level, x, y, z);
+                if case == 0 || case == 255 {
+                    continue;
+                }
+
+                let crossings = cell_crossings(table, &values, case, origin, x, y, z);
+                let triangulation = cell_triangles(table, &values, case, |e| crossings[e as usize]);
+
+                let crossing_normals = match normals {
+                    NormalMode::Face => [Vec3::ZERO; 12],
+                    NormalMode::Gradient => cell_normals(field, table, &values, case, [x, y, z], scale),
+                };
+
+                // Each crossing adds at most one vertex inside the cell
+                let mut vertices = [0_u32; 24];
+                for (e, &(a, b)) in table.shape.edges().iter().enumerate() {
+                    if positive(case, a) == positive(case, b) {
+                        continue;
+                    }
+
+                    // Edges run from the lower corner along a single axis
+                    let (cx, cy, cz) = CORNERS[a];
+                    let axis = (a ^ b).trailing_zeros() as usize;
+                    let key = 3 * index(x + cx, y + cy, z + cz, width, height) + axis;
+                    vertices[e] = *edge_vertices.entry(key).or_insert_with(|| {
+                        mesh_vertices.push((crossings[e] * scale).into());
+                        vertex_normals.push(crossing_normals[e]);
+                        vertex_keys.push(key);
+                        (mesh_vertices.len() - 1) as u32
+                    });
+                }
+                for (i, edges) in triangulation.centers.iter().enumerate() {
+                    mesh_vertices.push((center(&crossings, edges) * scale).into());
+                    vertex_normals.push(center(&crossing_normals, edges));
+                    vertex_keys.push(INSIDE_KEY);
+                    vertices[12 + i] = (mesh_vertices.len() - 1) as u32;
+                }
+
+                for &triangle in &triangulation.triangles {
+                    let [i0, i1, i2] = triangle.map(|e| vertices[e as usize]);
+                    let v0 = Vec3::from(mesh_vertices[i0 as usize]);
+                    let v1 = Vec3::from(mesh_vertices[i1 as usize]);
+                    let v2 = Vec3::from(mesh_vertices[i2 as usize]);
+
+                    if normals == NormalMode::Face {
+                        // Not normalized, larger triangles get a larger weight
+                        let normal = (v1 - v0).cross(v2 - v0);
+                        vertex_normals[i0 as usize] += normal;
+                        vertex_normals[i1 as usize] += normal;
+                        vertex_normals[i2 as usize] += normal;
+                    }
+
+                    mesh_indices.push(i0);
+                    mesh_indices.push(i1);
+                    mesh_indices.push(i2);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::mesh::Mesh;
+    use crate::test_util::{cube_rotations, edge_counts, noise, FnField};
+    use crate::{marching_cubes_indexed_with, MarchingCubesMode, MeshingOptions, ScalarField};
+
+    /// MC33 mesh of `field`
+    fn mc33<F: ScalarField>(field: &F) -> Mesh {
+        marching_cubes_indexed_with(field, MeshingOptions::new().mode(MarchingCubesMode::Mc33))
+    }
+
+    /// Whether every edge of the mesh is shared by exactly two triangles
+    fn is_closed(mesh: &Mesh) -> bool {
+        edge_counts(mesh).into_values().all(|n| n == 2)
+    }
+
+    /// Number of connected pieces of the mesh
+    fn num_components(mesh: &Mesh) -> usize {
+        fn root(parent: &[usize], mut v: usize) -> usize {
+            while parent[v] != v {
+                v = parent[v];
+            }
+            v
+        }
+
+        let mut parent: Vec<usize> = (0..mesh.num_vertices()).collect();
+        for t in mesh.triangle_indices() {
+            for &v in &t[1..] {
+                let (a, b) = (root(&parent, t[0] as usize), root(&parent, v as usize));
+                parent[a] = b;
+            }
+        }
+        (0..parent.len()).filter(|&v| root(&parent, v) == v).count()
+    }
+
+    /// Field that is outside on its border, so that the surface cannot leave
+    /// it, with `values` at the corners of the cell in the middle, x varying
+    /// fastest
+    fn middle_cell(values: [f32; 8]) -> FnField<impl Fn([usize; 3]) -> f32 + Sync> {
+        FnField {
+            dims: [4, 4, 4],
+            f: move |[x, y, z]: [usize; 3]| {
+                if [x, y, z].iter().any(|&c| c == 0 || c == 3) {
+                    return 1.0;
+                }
+                values[(x - 1) + 2 * (y - 1) + 4 * (z - 1)]
+            },
+        }
+    }
+
+    #[test]
+    fn every_case_is_closed() {
+        for case in 0..256 {
+            for seed in 0..8 {
+                let values = [0, 1, 2, 3, 4, 5, 6, 7].map(|corner| {
+                    let magnitude = 0.1 + 0.9 * noise([corner, case, 0], seed).abs();
+                    if case & (1 << corner) != 0 { magnitude } else { -magnitude }
+                });
+                assert!(is_closed(&mc33(&middle_cell(values))), "case {case}, seed {seed}");
+            }
+        }
+    }
+
+    #[test]
+    fn asymptotic_decider_resolves_face() {
+        // Inside corners on a diagonal of the z = 0 face, connected across
+        // the face if the saddle of the bilinear interpolant is inside
+        let face = |inside: f32, outside: f32| {
+            let mut values = [outside; 8];
+            values[0] = inside;
+            values[3] = inside;
+            mc33(&middle_cell(values))
+        };
+        assert_eq!(num_components(&face(-1.0, 0.1)), 1);
+        assert_eq!(num_components(&face(-0.1, 1.0)), 2);
+    }
+
+    #[test]
+    fn tunnel_test_resolves_interior() {
+        // Inside corners on a diagonal of the cell, joined by a tunnel if the
+        // trilinear interpolant is inside between them
+        let diagonal = |inside: f32, outside: f32| {
+            let mut values = [outside; 8];
+            values[0] = inside;
+            values[7] = inside;
+            mc33(&middle_cell(values))
+        };
+        assert_eq!(num_components(&diagonal(-1.0, 0.1)), 1);
+        assert_eq!(num_components(&diagonal(-0.1, 1.0)), 2);
+    }
+
+    #[test]
+    fn noise_is_closed() {
+        for seed in 0..8 {
+            let field = FnField {
+                dims: [10, 10, 10],
+                f: |p: [usize; 3]| {
+                    if p.iter().any(|&c| c == 0 || c == 9) { 1.0 } else { noise(p, seed) }
+                },
+            };
+            assert!(is_closed(&mc33(&field)), "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn cases_split_into_mc33_subcases() {
+        let rotations = cube_rotations();
+        let table = table();
+
+        // Smallest image of sign configuration `case` and its corner regions
+        // under rotation and inversion, each region labelled by the order it
+        // first appears in
+        let canonical = |case: usize, regions: &[[usize; 8]]| {
+            rotations
+                .iter()
+                .flat_map(|rotation| {
+                    [0, 255].map(|inverse| {
+                        let mut image = 0;
+                        for (c, &to) in rotation.iter().enumerate() {
+                            if positive(case ^ inverse, c) {
+                                image |= 1 << to;
+                            }
+                        }
+                        let regions: Vec<[usize; 8]> = regions
+                            .iter()
+                            .map(|region| {
+                                let mut moved = [0; 8];
+                                for c in 0..8 {
+                                    moved[rotation[c]] = rotation[region[c]];
+                                }
+                                let mut roots = Vec::new();
+                                moved.map(|root| {
+                                    roots.iter().position(|&r| r == root).unwrap_or_else(|| {
+                                        roots.push(root);
+                                        roots.len() - 1
+                                    })
+                                })
+                            })
+                            .collect();
+                        (image, regions)
+                    })
+                })
+                .min()
+                .unwrap()
+        };
+
+        // Subcases of each of Lorensen's 15 cases, told apart by the corners
+        // joined across faces and by those joined through the interior too
+        let mut subcases = HashMap::<usize, HashSet<Vec<[usize; 8]>>>::new();
+        for case in (0..256).filter(|&case| canonical(case, &[]).0 == case) {
+            for sample in 0..1000 {
+                let values = [0, 1, 2, 3, 4, 5, 6, 7].map(|corner| {
+                    let magnitude = 10_f32.powf(-2.0 + 2.0 * noise([corner, case, sample], 3).abs());
+                    if positive(case, corner) { magnitude } else { -magnitude }
+                });
+                let faces = face_resolution(&values, case);
+                let mut parent = boundary_regions(case, faces);
+                let boundary = [0, 1, 2, 3, 4, 5, 6, 7].map(|c| find(&mut parent, c));
+                if table.separated[64 * case + faces] {
+                    interior_links(&values, &mut parent);
+                }
+                let regions = [0, 1, 2, 3, 4, 5, 6, 7].map(|c| find(&mut parent, c));
+                subcases.entry(case).or_default().insert(canonical(case, &[boundary, regions]).1);
+            }
+        }
+        assert_eq!(subcases.len(), 15);
+
+        // Positive corners of the cases with more than one subcase, named as
+        // by Chernyaev
+        let split: [(&[usize], &[&str]); 7] = [
+            (&[0, 3], &["3.1", "3.2"]),
+            (&[0, 7], &["4.1.1", "4.1.2"]),
+            (&[0, 1, 7], &["6.1.1", "6.1.2", "6.2"]),
+            (&[1, 2, 4], &["7.1", "7.2", "7.3", "7.4.1", "7.4.2"]),
+            (&[2, 3, 4, 5], &["10.1.1", "10.1.2", "10.2"]),
+            (&[1, 2, 3, 4], &["12.1.1", "12.1.2", "12.2"]),
+            (&[0, 3, 5, 6], &["13.1", "13.2", "13.3", "13.4", "13.5.1", "13.5.2"]),
+        ];
+        for (case, found) in subcases {
+            let expected = split
+                .iter()
+                .find(|(corners, _)| canonical(corners.iter().map(|&c| 1 << c).sum(), &[]).0 == case)
+                .map_or(1, |(_, names)| names.len());
+            assert_eq!(found.len(), expected, "case {case:08b}");
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use glam::Vec3;
+
+use crate::degenerate::face_normal;
+use crate::field::{index, normalize_scale, ScalarField};
+use crate::marching_cubes::{interp_vertex, merge_slabs};
+use crate::mesh::Mesh;
+use crate::options::MeshingOptions;
+use crate::parallel::map_slabs;
+
+/// Offset of cell corner `i`, x varying fastest
+fn corner(i: usize) -> (usize, usize, usize) {
+    (i & 1, (i >> 1) & 1, i >> 2)
+}
+
+/// Tetrahedra of a cell as corner indices
+///
+/// All six share the diagonal from corner 0 to corner 7, so every face of the
+/// cell is split along the diagonal through its lowest corner, the same way
+/// as by the neighbouring cell. Each tetrahedron is a chain of corners where
+/// every corner adds one axis to the previous one.
+const TETRAHEDRA: [[usize; 4]; 6] = [
+    [0, 1, 3, 7],
+    [0, 1, 5, 7],
+    [0, 2, 3, 7],
+    [0, 2, 6, 7],
+    [0, 4, 5, 7],
+    [0, 4, 6, 7],
+];
+
+/// Triangles of each tetrahedron for each sign configuration of its corners
+///
+/// Bit `i` of the configuration is set if corner `i` of the tetrahedron is
+/// positive. Triangles are given as cell edges, pairs of cell corners with the
+/// lower corner first, and face the positive corners.
+type TriangleTable = Vec<Vec<Vec<[(usize, usize); 3]>>>;
+
+fn triangle_table() -> &'static TriangleTable {
+    static TABLE: OnceLock<TriangleTable> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let position = |c: usize| {
+            let (x, y, z) = corner(c);
+            Vec3::new(x as f32, y as f32, z as f32)
+        };
+
+        TETRAHEDRA
+            .iter()
+            .map(|tet| {
+                (0..16)
+                    .map(|case| {
+                        let corners = |positive: bool| -> Vec<usize> {
+                            tet.iter()
+                                .enumerate()
+                                .filter(|&(i, _)| (case & (1 << i) != 0) == positive)
+                                .map(|(_, &c)| c)
+                                .collect()
+                        };
+                        let (positive, negative) = (corners(true), corners(false));
+
+                        let edge = |a: usize, b: usize| (a.min(b), a.max(b));
+                        let triangles = match (positive.len(), negative.len()) {
+                            (1, 3) => vec![[
+                                edge(positive[0], negative[0]),
+                                edge(positive[0], negative[1]),
+                                edge(positive[0], negative[2]),
+                            ]],
+                            (3, 1) => vec![[
+                                edge(negative[0], positive[0]),
+                                edge(negative[0], positive[1]),
+                                edge(negative[0], positive[2]),
+                            ]],
+                            (2, 2) => {
+                                // Quad around the tetrahedron, adjacent edges share a corner
+                                let [a, b] = [positive[0], positive[1]];
+                                let [c, d] = [negative[0], negative[1]];
+                                vec![
+                                    [edge(a, c), edge(a, d), edge(b, d)],
+                                    [edge(a, c), edge(b, d), edge(b, c)],
+                                ]
+                            }
+                            _ => Vec::new(),
+                        };
+
+                        // Orient the triangles using the edge midpoints
+                        let towards_positive = positive.iter().map(|&c| position(c)).sum::<Vec3>() / positive.len().max(1) as f32
+                            - negative.iter().map(|&c| position(c)).sum::<Vec3>() / negative.len().max(1) as f32;
+                        triangles
+                            .into_iter()
+                            .map(|[e0, e1, e2]| {
+                                let m = |(a, b): (usize, usize)| (position(a) + position(b)) * 0.5;
+                                let normal = (m(e1) - m(e0)).cross(m(e2) - m(e0));
+                                if normal.dot(towards_positive) < 0.0 {
+                                    [e0, e2, e1]
+                                } else {
+                                    [e0, e1, e2]
+                                }
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect()
+    })
+}
+
+/// Configuration of each tetrahedron of a cell, given the bit set of positive
+/// cell corners
+fn tetrahedron_case(tet: &[usize; 4], cell_case: usize) -> usize {
+    let mut case = 0;
+    for (i, &c) in tet.iter().enumerate() {
+        case |= ((cell_case >> c) & 1) << i;
+    }
+    case
+}
+
+/// Key identifying the grid edge between corners `a` and `b` of cell (x, y, z),
+/// shared by all cells touching the edge
+fn edge_key(x: usize, y: usize, z: usize, (a, b): (usize, usize), width: usize, height: usize) -> usize {
+    // The lower corner's bits are a subset of the upper corner's
+    let (cx, cy, cz) = corner(a);
+    8 * index(x + cx, y + cy, z + cz, width, height) + (a ^ b)
+}
+
+/// Marching tetrahedra, splitting each cell into six tetrahedra
+///
+/// Extracts the same isosurface as [`crate::marching_cubes`], but as every
+/// tetrahedron has a single way to separate its corners, there are no
+/// ambiguous cases and the output is watertight. This comes at the cost of
+/// about three times as many triangles.
+pub fn marching_tetrahedra<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+) -> Mesh {
+    extract(field, iso_level, normalize_scale(field))
+}
+
+/// [`marching_tetrahedra`] with the given options, see
+/// [`crate::marching_cubes_with`]
+pub fn marching_tetrahedra_with<F: ScalarField + ?Sized>(
+    field: &F,
+    options: MeshingOptions,
+) -> Mesh {
+    let grid = options.grid(field);
+    options.finish(extract(field, options.iso_level, grid.spacing), &grid)
+}
+
+/// Marching tetrahedra with vertices scaled by `scale`
+fn extract<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    scale: Vec3,
+) -> Mesh {
+    let [width, height, depth] = field.dims();
+    let table = triangle_table();
+
+    let slabs = map_slabs(depth - 1, |layers| {
+        let mut mesh_vertices = Vec::<[f32;3]>::new();
+        let mut mesh_normals = Vec::<[f32;3]>::new();
+
+        for z in layers {
+            for y in 0..height-1 {
+                for x in 0..width-1 {
+                    let mut corner_densities = [0.0_f32; 8];
+                    let mut cell_case = 0;
+                    for (c, density) in corner_densities.iter_mut().enumerate() {
+                        let (cx, cy, cz) = corner(c);
+                        *density = field.sample_relative(x + cx, y + cy, z + cz, iso_level);
+                        cell_case |= ((*density > 0.0) as usize) << c;
+                    }
+
+                    if cell_case == 0 || cell_case == 255 {
+                        continue;
+                    }
+
+                    let vertex = |(a, b): (usize, usize)| {
+                        let p = |c: usize| {
+                            let (cx, cy, cz) = corner(c);
+                            Vec3::new((x + cx) as f32, (y + cy) as f32, (z + cz) as f32)
+                        };
+                        interp_vertex(p(a), p(b), corner_densities[a], corner_densities[b], 0.0) * scale
+                    };
+
+                    for (t, tet) in TETRAHEDRA.iter().enumerate() {
+                        for &[e0, e1, e2] in &table[t][tetrahedron_case(tet, cell_case)] {
+                            let (v0, v1, v2) = (vertex(e0), vertex(e1), vertex(e2));
+
+                            mesh_vertices.push(v0.into());
+                            mesh_vertices.push(v1.into());
+                            mesh_vertices.push(v2.into());
+
+                            let normal = face_normal(v0, v1, v2);
+                            mesh_normals.push(normal.into());
+                            mesh_normals.push(normal.into());
+                            mesh_normals.push(normal.into());
+                        }
+                    }
+                }
+            }
+        }
+        (mesh_vertices, mesh_normals)
+    });
+
+    let mut mesh_vertices = Vec::<[f32;3]>::new();
+    let mut mesh_normals = Vec::<[f32;3]>::new();
+    for (vertices, normals) in slabs {
+        mesh_vertices.extend(vertices);
+        mesh_normals.extend(normals);
+    }
+    Mesh::new(mesh_vertices, mesh_normals)
+}
+
+/// Marching tetrahedra producing an indexed mesh, see [`crate::marching_cubes_indexed`]
+pub fn marching_tetrahedra_indexed<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+) -> Mesh {
+    extract_indexed(field, iso_level, normalize_scale(field))
+}
+
+/// [`marching_tetrahedra_indexed`] with the given options, see
+/// [`crate::marching_cubes_with`]
+pub fn marching_tetrahedra_indexed_with<F: ScalarField + ?Sized>(
+    field: &F,
+    options: MeshingOptions,
+) -> Mesh {
+    let grid = options.grid(field);
+    options.finish(extract_indexed(field, options.iso_level, grid.spacing), &grid)
+}
+
+/// Indexed version of [`extract`]
+fn extract_indexed<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    scale: Vec3,
+) -> Mesh {
+    let [width, height, depth] = field.dims();
+    let table = triangle_table();
+
+    let slabs = map_slabs(depth - 1, |layers| {
+        let mut mesh_vertices = Vec::<[f32;3]>::new();
+        let mut vertex_normals = Vec::<Vec3>::new();
+        let mut vertex_keys = Vec::<usize>::new();
+        let mut mesh_indices = Vec::<u32>::new();
+
+        // Grid edge -> vertex index
+        let mut edge_vertices = HashMap::<usize, u32>::new();
+        let mut cell_triangles = Vec::<[u32; 3]>::new();
+
+        for z in layers {
+            for y in 0..height-1 {
+                for x in 0..width-1 {
+                    let mut corner_densities = [0.0_f32; 8];
+                    let mut cell_case = 0;
+                    for (c, density) in corner_densities.iter_mut().enumerate() {
+                        let (cx, cy, cz) = corner(c);
+                        *density = field.sample_relative(x + cx, y + cy, z + cz, iso_level);
+                        cell_case |= ((*density > 0.0) as usize) << c;
+                    }
+
+                    if cell_case == 0 || cell_case == 255 {
+                        continue;
+                    }
+
+                    let mut vertex = |(a, b): (usize, usize)| {
+                        let key = edge_key(x, y, z, (a, b), width, height);
+                        *edge_vertices.entry(key).or_insert_with(|| {
+                            let p = |c: usize| {
+                                let (cx, cy, cz) = corner(c);
+                                Vec3::new((x + cx) as f32, (y + cy) as f32, (z + cz) as f32)
+                            };
+                            let v = interp_vertex(p(a), p(b), corner_densities[a], corner_densities[b], 0.0);
+
+                            mesh_vertices.push((v * scale).into());
+                            vertex_normals.push(Vec3::ZERO);
+                            vertex_keys.push(key);
+                            (mesh_vertices.len() - 1) as u32
+                        })
+                    };
+
+                    cell_triangles.clear();
+                    for (t, tet) in TETRAHEDRA.iter().enumerate() {
+                        for &triangle in &table[t][tetrahedron_case(tet, cell_case)] {
+                            cell_triangles.push(triangle.map(&mut vertex));
+                        }
+                    }
+
+                    for &[i0, i1, i2] in &cell_triangles {
+                        let v0 = Vec3::from(mesh_vertices[i0 as usize]);
+                        let v1 = Vec3::from(mesh_vertices[i1 as usize]);
+                        let v2 = Vec3::from(mesh_vertices[i2 as usize]);
+
+                        // Not normalized, larger triangles get a larger weight
+                        let normal = (v1 - v0).cross(v2 - v0);
+                        vertex_normals[i0 as usize] += normal;
+                        vertex_normals[i1 as usize] += normal;
+                        vertex_normals[i2 as usize] += normal;
+
+                        mesh_indices.push(i0);
+                        mesh_indices.push(i1);
+                        mesh_indices.push(i2);
+                    }
+                }
+            }
+        }
+        (mesh_vertices, vertex_normals, vertex_keys, mesh_indices)
+    });
+
+    merge_slabs(slabs, depth, |key| key / 8 / (width * height)).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use glam::Vec3;
+
+    use super::*;
+    use crate::geometry::GridGeometry;
+    use crate::test_util::{bordered_noise, edge_counts, signed_volume, sphere};
+
+    #[test]
+    fn noise_is_watertight() {
+        for seed in 0..8 {
+            let field = bordered_noise([10; 3], seed);
+            for mesh in [marching_tetrahedra(&field, 0.0), marching_tetrahedra_indexed(&field, 0.0)] {
+                assert!(!mesh.is_empty());
+                assert!(edge_counts(&mesh).into_values().all(|n| n == 2), "seed {seed}");
+            }
+        }
+    }
+
+    #[test]
+    fn sphere_faces_outwards() {
+        let field = sphere([16; 3], Vec3::splat(7.5), 5.3);
+        let options = MeshingOptions::new().geometry(GridGeometry::default());
+        let volume = 4.0 / 3.0 * PI * 5.3_f32.powi(3);
+        for mesh in [marching_tetrahedra_with(&field, options), marching_tetrahedra_indexed_with(&field, options)] {
+            assert!((signed_volume(&mesh) / volume - 1.0).abs() < 0.05, "{}", signed_volume(&mesh));
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use glam::{Affine3A, Vec3};
+
+use crate::degenerate::{degenerate, remove_indexed, remove_soup, unit_normal};
+
+/// Mesh returned by the extractors
+///
+/// Without indices the mesh is a triangle soup, every three vertices forming
+/// a triangle. Triangles are counter-clockwise seen from outside the surface,
+/// where the normals point, and every normal is of unit length. Besides
+/// positions and normals, vertices may carry colors, texture coordinates and
+/// material ids, which the extractors leave empty. Each channel that is set
+/// holds one value per vertex.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh {
+    pub positions: Vec<[f32;3]>,
+    pub normals: Vec<[f32;3]>,
+    /// Vertex indices of the triangles, or of the quads if `quads` is set
+    pub indices: Option<Vec<u32>>,
+    /// Whether `indices` holds four vertices per quad rather than three per
+    /// triangle
+    pub quads: bool,
+    pub colors: Option<Vec<[f32;4]>>,
+    pub uvs: Option<Vec<[f32;2]>>,
+    pub material_ids: Option<Vec<u32>>,
+    /// Number of triangles with zero area removed from the mesh, see
+    /// [`Mesh::remove_degenerate`]
+    pub removed_degenerate: usize,
+}
+
+impl Mesh {
+    /// Triangle soup
+    pub fn new(positions: Vec<[f32;3]>, normals: Vec<[f32;3]>) -> Self {
+        Self {
+            positions,
+            normals,
+            ..Default::default()
+        }
+    }
+
+    /// Indexed triangle mesh
+    pub fn indexed(positions: Vec<[f32;3]>, normals: Vec<[f32;3]>, indices: Vec<u32>) -> Self {
+        Self {
+            indices: Some(indices),
+            ..Self::new(positions, normals)
+        }
+    }
+
+    /// Indexed mesh of quads
+    pub fn quads(positions: Vec<[f32;3]>, normals: Vec<[f32;3]>, indices: Vec<u32>) -> Self {
+        Self {
+            quads: true,
+            ..Self::indexed(positions, normals, indices)
+        }
+    }
+
+    pub fn num_vertices(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Number of triangles, counting two per quad
+    pub fn num_triangles(&self) -> usize {
+        match &self.indices {
+            Some(indices) if self.quads => 2 * (indices.len() / 4),
+            Some(indices) => indices.len() / 3,
+            None => self.positions.len() / 3,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_triangles() == 0
+    }
+
+    /// Minimum and maximum corner of the box around the vertices, `None` if
+    /// there are none
+    pub fn bounding_box(&self) -> Option<(Vec3, Vec3)> {
+        let first = Vec3::from(*self.positions.first()?);
+        Some(self.positions.iter().fold((first, first), |(min, max), &p| {
+            (min.min(p.into()), max.max(p.into()))
+        }))
+    }
+
+    /// Vertex indices of every triangle, with quads split in two along their
+    /// first diagonal
+    pub fn triangle_indices(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        let (indices, sides) = match &self.indices {
+            Some(indices) => (Some(indices), if self.quads { 4 } else { 3 }),
+            None => (None, 3),
+        };
+        let num_polygons = match indices {
+            Some(indices) => indices.len() / sides,
+            None => self.positions.len() / 3,
+        };
+
+        (0..num_polygons).flat_map(move |p| {
+            let v = |i: usize| match indices {
+                Some(indices) => indices[sides * p + i],
+                None => (3 * p + i) as u32,
+            };
+            let second = (sides == 4).then(|| [v(0), v(2), v(3)]);
+            std::iter::once([v(0), v(1), v(2)]).chain(second)
+        })
+    }
+
+    /// Vertex positions of every triangle, see [`Mesh::triangle_indices`]
+    pub fn triangles(&self) -> impl Iterator<Item = [Vec3; 3]> + '_ {
+        self.triangle_indices()
+            .map(|t| t.map(|v| Vec3::from(self.positions[v as usize])))
+    }
+
+    /// Splits the quads into triangles, see [`Mesh::triangle_indices`]
+    pub fn triangulate(&mut self) {
+        if self.quads {
+            self.indices = Some(self.triangle_indices().flatten().collect());
+            self.quads = false;
+        }
+    }
+
+    /// Appends the vertices and polygons of `other`
+    ///
+    /// The result is a soup if both meshes are, otherwise indexed, and made
+    /// of quads if both are. Channels set on only one of the meshes are
+    /// filled with white, zero texture coordinates and material 0 for the
+    /// vertices of the other.
+    pub fn merge(&mut self, other: &Mesh) {
+        let (num_vertices, num_other) = (self.positions.len(), other.positions.len());
+
+        if self.indices.is_some() || other.indices.is_some() {
+            let mut other = other.clone();
+            if self.quads != other.quads {
+                self.triangulate();
+                other.triangulate();
+            }
+
+            let indices = self.indices.get_or_insert_with(|| (0..num_vertices as u32).collect());
+            match &other.indices {
+                Some(other_indices) => indices.extend(other_indices.iter().map(|&i| i + num_vertices as u32)),
+                None => indices.extend(num_vertices as u32..(num_vertices + num_other) as u32),
+            }
+        }
+
+        self.positions.extend_from_slice(&other.positions);
+        self.normals.extend_from_slice(&other.normals);
+        merge_channel(&mut self.colors, &other.colors, num_vertices, num_other, [1.0; 4]);
+        merge_channel(&mut self.uvs, &other.uvs, num_vertices, num_other, [0.0; 2]);
+        merge_channel(&mut self.material_ids, &other.material_ids, num_vertices, num_other, 0);
+        self.removed_degenerate += other.removed_degenerate;
+    }
+
+    /// Applies `transform` to the positions and normals
+    ///
+    /// Normals are transformed by the inverse transpose, so they stay
+    /// perpendicular to the surface under non-uniform scaling. Transforms that
+    /// mirror the mesh also flip the winding, keeping the triangles
+    /// counter-clockwise seen from where the normals point.
+    pub fn transform(&mut self, transform: Affine3A) {
+        let normal_matrix = transform.matrix3.inverse().transpose();
+
+        for p in &mut self.positions {
+            *p = transform.transform_point3((*p).into()).into();
+        }
+        for n in &mut self.normals {
+            *n = unit_normal(normal_matrix * Vec3::from(*n)).into();
+        }
+
+        if transform.matrix3.determinant() < 0.0 {
+            self.flip_winding();
+        }
+    }
+
+    /// Reverses the order of the vertices of every polygon, turning
+    /// counter-clockwise triangles clockwise
+    ///
+    /// Normals are left as they are.
+    pub fn flip_winding(&mut self) {
+        match &mut self.indices {
+            Some(indices) => {
+                for polygon in indices.chunks_exact_mut(if self.quads { 4 } else { 3 }) {
+                    polygon[1..].reverse();
+                }
+            }
+            None => {
+                for t in 0..self.positions.len() / 3 {
+                    let (a, b) = (3 * t + 1, 3 * t + 2);
+                    self.positions.swap(a, b);
+                    self.normals.swap(a, b);
+                    swap_channel(&mut self.colors, a, b);
+                    swap_channel(&mut self.uvs, a, b);
+                    swap_channel(&mut self.material_ids, a, b);
+                }
+            }
+        }
+    }
+
+    /// Removes the triangles with zero area, see
+    /// [`crate::remove_degenerate_triangles`] and
+    /// [`crate::remove_degenerate_indexed`]
+    ///
+    /// Meshes of quads with a triangle of zero area, see
+    /// [`Mesh::triangle_indices`], are triangulated first. Returns the number
+    /// of triangles removed, which is also added to
+    /// [`Mesh::removed_degenerate`].
+    pub fn remove_degenerate(&mut self) -> usize {
+        if self.quads {
+            if !self.triangles().any(|[v0, v1, v2]| degenerate(v0, v1, v2)) {
+                return 0;
+            }
+            self.triangulate();
+        }
+
+        let num_triangles = self.num_triangles();
+        let sources = match &mut self.indices {
+            Some(indices) => remove_indexed(&mut self.positions, &mut self.normals, indices),
+            None => remove_soup(&mut self.positions, &mut self.normals),
+        };
+        gather_channel(&mut self.colors, &sources);
+        gather_channel(&mut self.uvs, &sources);
+        gather_channel(&mut self.material_ids, &sources);
+
+        let num_removed = num_triangles - self.num_triangles();
+        self.removed_degenerate += num_removed;
+        num_removed
+    }
+
+    /// Removes the vertices of an indexed mesh that no polygon uses, keeping
+    /// the order of the others
+    pub(crate) fn remove_unused_vertices(&mut self) {
+        let Some(indices) = &mut self.indices else {
+            return;
+        };
+
+        let mut new_index = vec![u32::MAX; self.positions.len()];
+        for &i in indices.iter() {
+            new_index[i as usize] = 0;
+        }
+        let mut sources = Vec::with_capacity(self.positions.len());
+        for (v, new) in new_index.iter_mut().enumerate() {
+            if *new == 0 {
+                *new = sources.len() as u32;
+                sources.push(v as u32);
+            }
+        }
+        for i in indices.iter_mut() {
+            *i = new_index[*i as usize];
+        }
+
+        self.positions = sources.iter().map(|&v| self.positions[v as usize]).collect();
+        self.normals = sources.iter().map(|&v| self.normals[v as usize]).collect();
+        gather_channel(&mut self.colors, &sources);
+        gather_channel(&mut self.uvs, &sources);
+        gather_channel(&mut self.material_ids, &sources);
+    }
+}
+
+impl From<(Vec<[f32;3]>, Vec<[f32;3]>)> for Mesh {
+    fn from((positions, normals): (Vec<[f32;3]>, Vec<[f32;3]>)) -> Self {
+        Self::new(positions, normals)
+    }
+}
+
+impl From<(Vec<[f32;3]>, Vec<[f32;3]>, Vec<u32>)> for Mesh {
+    fn from((positions, normals, indices): (Vec<[f32;3]>, Vec<[f32;3]>, Vec<u32>)) -> Self {
+        Self::indexed(positions, normals, indices)
+    }
+}
+
+fn swap_channel<T>(channel: &mut Option<Vec<T>>, a: usize, b: usize) {
+    if let Some(channel) = channel {
+        channel.swap(a, b);
+    }
+}
+
+/// Replaces `channel` by the values of the vertices in `sources`
+fn gather_channel<T: Copy>(channel: &mut Option<Vec<T>>, sources: &[u32]) {
+    if let Some(channel) = channel {
+        *channel = sources.iter().map(|&v| channel[v as usize]).collect();
+    }
+}
+
+/// Appends `other` to `channel`, where the channels belong to meshes of
+/// `len` and `other_len` vertices and missing ones are filled with `default`
+fn merge_channel<T: Clone>(
+    channel: &mut Option<Vec<T>>,
+    other: &Option<Vec<T>>,
+    len: usize,
+    other_len: usize,
+    default: T,
+) {
+    if channel.is_none() && other.is_none() {
+        return;
+    }
+
+    let channel = channel.get_or_insert_with(|| vec![default.clone(); len]);
+    match other {
+        Some(other) => channel.extend_from_slice(other),
+        None => channel.resize(len + other_len, default),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{signed_volume, soup, sphere};
+    use crate::{dual_contouring_indexed_with, marching_cubes_indexed_with, marching_cubes_with, MeshingOptions};
+
+    fn spheres() -> [Mesh; 3] {
+        let field = sphere([12; 3], Vec3::splat(5.5), 3.7);
+        let options = MeshingOptions::new();
+        [
+            marching_cubes_with(&field, options),
+            marching_cubes_indexed_with(&field, options),
+            dual_contouring_indexed_with(&field, true, options),
+        ]
+    }
+
+    #[test]
+    fn merge_offsets_indices() {
+        let spheres = spheres();
+        for a in &spheres {
+            for b in &spheres {
+                let mut merged = a.clone();
+                let mut moved = b.clone();
+                moved.transform(Affine3A::from_translation(Vec3::X));
+                merged.merge(&moved);
+
+                assert_eq!(merged.num_vertices(), a.num_vertices() + b.num_vertices());
+                assert_eq!(merged.num_triangles(), a.num_triangles() + b.num_triangles());
+                assert!(merged.triangles().eq(a.triangles().chain(moved.triangles())));
+            }
+        }
+    }
+
+    #[test]
+    fn flip_winding_negates_volume() {
+        for mesh in spheres() {
+            let volume = signed_volume(&soup(&mesh));
+            assert!(volume > 0.0);
+
+            let mut flipped = mesh.clone();
+            flipped.flip_winding();
+            let flipped_volume = signed_volume(&soup(&flipped));
+            assert!((flipped_volume + volume).abs() < 1e-3 * volume, "{volume} {flipped_volume}");
+
+            flipped.flip_winding();
+            assert_eq!(flipped, mesh);
+        }
+    }
+}
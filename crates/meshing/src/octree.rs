@@ -0,0 +1,387 @@
+use glam::Vec3;
+
+use crate::degenerate::face_normal;
+use crate::dual_contouring::{cell_qef, CORNERS};
+use crate::field::{normalize_scale, ScalarField};
+use crate::mesh::Mesh;
+use crate::options::MeshingOptions;
+use crate::qef::{Qef, QefOptions};
+
+/// Corners of each cube edge, grouped by the axis of the edge
+const EDGE_CORNERS: [(usize, usize); 12] = [
+    (0, 4), (1, 5), (2, 6), (3, 7),
+    (0, 2), (1, 3), (4, 6), (5, 7),
+    (0, 1), (2, 3), (4, 5), (6, 7),
+];
+
+/// Child pairs sharing a face within a cell, and the axis of the face
+const CELL_PROC_FACES: [(usize, usize, usize); 12] = [
+    (0, 4, 0), (1, 5, 0), (2, 6, 0), (3, 7, 0),
+    (0, 2, 1), (4, 6, 1), (1, 3, 1), (5, 7, 1),
+    (0, 1, 2), (2, 3, 2), (4, 5, 2), (6, 7, 2),
+];
+
+/// Children around an edge within a cell, and the axis of the edge
+const CELL_PROC_EDGES: [([usize; 4], usize); 6] = [
+    ([0, 1, 2, 3], 0), ([4, 5, 6, 7], 0),
+    ([0, 4, 1, 5], 1), ([2, 6, 3, 7], 1),
+    ([0, 2, 4, 6], 2), ([1, 3, 5, 7], 2),
+];
+
+/// Child pairs sharing a face within a face, per face axis
+const FACE_PROC_FACES: [[(usize, usize, usize); 4]; 3] = [
+    [(4, 0, 0), (5, 1, 0), (6, 2, 0), (7, 3, 0)],
+    [(2, 0, 1), (6, 4, 1), (3, 1, 1), (7, 5, 1)],
+    [(1, 0, 2), (3, 2, 2), (5, 4, 2), (7, 6, 2)],
+];
+
+/// Children around an edge within a face, per face axis
+///
+/// The first entry selects which of the two nodes each child is taken from,
+/// see [`FACE_PROC_ORDERS`].
+const FACE_PROC_EDGES: [[(usize, [usize; 4], usize); 4]; 3] = [
+    [(1, [4, 0, 5, 1], 1), (1, [6, 2, 7, 3], 1), (0, [4, 6, 0, 2], 2), (0, [5, 7, 1, 3], 2)],
+    [(0, [2, 3, 0, 1], 0), (0, [6, 7, 4, 5], 0), (1, [2, 0, 6, 4], 2), (1, [3, 1, 7, 5], 2)],
+    [(1, [1, 0, 3, 2], 0), (1, [5, 4, 7, 6], 0), (0, [1, 5, 0, 4], 1), (0, [3, 7, 2, 6], 1)],
+];
+
+const FACE_PROC_ORDERS: [[usize; 4]; 2] = [[0, 0, 1, 1], [0, 1, 0, 1]];
+
+/// Children around an edge within an edge, per edge axis
+const EDGE_PROC_EDGES: [[[usize; 4]; 2]; 3] = [
+    [[3, 2, 1, 0], [7, 6, 5, 4]],
+    [[5, 1, 4, 0], [7, 3, 6, 2]],
+    [[6, 4, 2, 0], [7, 5, 3, 1]],
+];
+
+/// Edge of each of the four nodes around an edge that is the shared edge
+const PROCESS_EDGES: [[usize; 4]; 3] = [[3, 2, 1, 0], [7, 5, 6, 4], [11, 10, 9, 8]];
+
+enum Node {
+    Internal(Box<[Option<Node>; 8]>),
+    Leaf(Leaf),
+}
+
+/// Cell, or collapsed group of cells, containing the surface
+struct Leaf {
+    /// Edge length in cells
+    size: usize,
+    /// Bit `i` is set if corner `i` is inside
+    corners: u8,
+    /// QEF of the tangent planes of all crossings within the leaf, in grid
+    /// coordinates
+    qef: Qef,
+    vertex: Vec3,
+}
+
+impl Leaf {
+    fn inside(&self, corner: usize) -> bool {
+        self.corners & (1 << corner) != 0
+    }
+}
+
+/// Implements T Ju, et al., Dual Contouring of Hermite Data, with octree simplification
+///
+/// Builds an octree over the cells of `field` and collapses every group of
+/// eight leaves whose combined QEF error is below `max_error`, replacing them
+/// with a single vertex. Flat regions thus end up with far fewer quads than
+/// with [`crate::dual_contouring`], while a `max_error` of 0 collapses
+/// nothing. The error is the sum of the squared distances from the vertex to
+/// the tangent planes of the collapsed cells, in grid units.
+///
+/// Only the QEF error is checked when collapsing, so large thresholds may
+/// merge nearby sheets of the surface or close small holes.
+pub fn dual_contouring_octree<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    max_error: f32,
+) -> Mesh {
+    extract(field, iso_level, max_error, &QefOptions::default(), normalize_scale(field))
+}
+
+/// [`dual_contouring_octree`] with the given options, see
+/// [`crate::dual_contouring_with`]
+pub fn dual_contouring_octree_with<F: ScalarField + ?Sized>(
+    field: &F,
+    max_error: f32,
+    options: MeshingOptions,
+) -> Mesh {
+    let grid = options.grid(field);
+    let mesh = extract(field, options.iso_level, max_error, &options.qef, grid.spacing);
+    options.finish(mesh, &grid)
+}
+
+/// Octree dual contouring with vertices scaled by `scale`
+fn extract<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    max_error: f32,
+    options: &QefOptions,
+    scale: Vec3,
+) -> Mesh {
+    let [width, height, depth] = field.dims();
+    let builder = Builder {
+        field,
+        iso_level,
+        max_error,
+        options,
+        cells: [width - 1, height - 1, depth - 1],
+    };
+    let size = (width - 1).max(height - 1).max(depth - 1).next_power_of_two();
+    let root = builder.build([0, 0, 0], size);
+
+    let mut mesh_positions = Vec::<[f32;3]>::new();
+    let mut mesh_normals = Vec::<[f32;3]>::new();
+    let mut emit = |leaves: [&Leaf; 3]| {
+        // Nodes passed down twice give degenerate triangles
+        if std::ptr::eq(leaves[0], leaves[1])
+            || std::ptr::eq(leaves[1], leaves[2])
+            || std::ptr::eq(leaves[2], leaves[0])
+        {
+            return;
+        }
+
+        let [v0, v1, v2] = leaves.map(|leaf| leaf.vertex * scale);
+        mesh_positions.push(v0.into());
+        mesh_positions.push(v1.into());
+        mesh_positions.push(v2.into());
+
+        let normal = face_normal(v0, v1, v2);
+        mesh_normals.push(normal.into());
+        mesh_normals.push(normal.into());
+        mesh_normals.push(normal.into());
+    };
+
+    cell_proc(root.as_ref(), &mut emit);
+
+    Mesh::new(mesh_positions, mesh_normals)
+}
+
+struct Builder<'a, F: ?Sized> {
+    field: &'a F,
+    iso_level: f32,
+    max_error: f32,
+    /// Placement of the vertices in their cells
+    options: &'a QefOptions,
+    /// Number of cells along x, y and z
+    cells: [usize; 3],
+}
+
+impl<F: ScalarField + ?Sized> Builder<'_, F> {
+    /// Builds the subtree with minimum cell `min`, or `None` if it does not
+    /// contain the surface
+    fn build(&self, min: [usize; 3], size: usize) -> Option<Node> {
+        if (0..3).any(|i| min[i] >= self.cells[i]) {
+            return None;
+        }
+
+        if size == 1 {
+            return self.cell(min).map(Node::Leaf);
+        }
+
+        let half = size / 2;
+        let children = Box::new(CORNERS.map(|c| {
+            self.build([min[0] + c.0 * half, min[1] + c.1 * half, min[2] + c.2 * half], half)
+        }));
+
+        if children.iter().all(Option::is_none) {
+            return None;
+        }
+
+        match self.collapse(min, size, &children) {
+            Some(leaf) => Some(Node::Leaf(leaf)),
+            None => Some(Node::Internal(children)),
+        }
+    }
+
+    /// Leaf of a single cell
+    fn cell(&self, min: [usize; 3]) -> Option<Leaf> {
+        let corners = self.corners(min, 1);
+        if corners == 0 || corners == u8::MAX {
+            return None;
+        }
+
+        let offset = Vec3::new(min[0] as f32, min[1] as f32, min[2] as f32);
+        let qef = cell_qef(self.field, self.iso_level, min, offset);
+
+        let (vertex, _) = solve(&qef, min, 1, self.options);
+        Some(Leaf {
+            size: 1,
+            corners,
+            qef,
+            vertex,
+        })
+    }
+
+    /// Merges `children` into a single leaf if they are all leaves and their
+    /// combined error is below the threshold
+    fn collapse(&self, min: [usize; 3], size: usize, children: &[Option<Node>; 8]) -> Option<Leaf> {
+        if (0..3).any(|i| min[i] + size > self.cells[i]) {
+            return None;
+        }
+
+        let mut qef = Qef::new();
+        for child in children.iter().flatten() {
+            match child {
+                Node::Leaf(leaf) => qef.merge(&leaf.qef),
+                Node::Internal(_) => return None,
+            }
+        }
+
+        let (vertex, error) = solve(&qef, min, size, self.options);
+        if error >= self.max_error {
+            return None;
+        }
+
+        Some(Leaf {
+            size,
+            corners: self.corners(min, size),
+            qef,
+            vertex,
+        })
+    }
+
+    /// Inside flags of the corners of the cube at `min` with edge length `size`
+    fn corners(&self, min: [usize; 3], size: usize) -> u8 {
+        let mut corners = 0;
+        for (i, c) in CORNERS.iter().enumerate() {
+            let density = self.field.sample_relative(
+                min[0] + c.0 * size,
+                min[1] + c.1 * size,
+                min[2] + c.2 * size,
+                self.iso_level,
+            );
+            if density <= 0.0 {
+                corners |= 1 << i;
+            }
+        }
+        corners
+    }
+}
+
+/// Minimizes `qef` within the cube at `min` with edge length `size`,
+/// returning the vertex and its error
+fn solve(qef: &Qef, min: [usize; 3], size: usize, options: &QefOptions) -> (Vec3, f32) {
+    let min = Vec3::new(min[0] as f32, min[1] as f32, min[2] as f32);
+    qef.solve_in_cell(min, min + Vec3::splat(size as f32), options)
+}
+
+fn child(node: &Node, i: usize) -> Option<&Node> {
+    match node {
+        Node::Internal(children) => children[i].as_ref(),
+        Node::Leaf(_) => Some(node),
+    }
+}
+
+fn cell_proc<'a>(node: Option<&'a Node>, emit: &mut impl FnMut([&'a Leaf; 3])) {
+    let Some(Node::Internal(children)) = node else {
+        return;
+    };
+
+    for child in children.iter() {
+        cell_proc(child.as_ref(), emit);
+    }
+
+    for (a, b, axis) in CELL_PROC_FACES {
+        face_proc([children[a].as_ref(), children[b].as_ref()], axis, emit);
+    }
+
+    for (c, axis) in CELL_PROC_EDGES {
+        edge_proc(c.map(|i| children[i].as_ref()), axis, emit);
+    }
+}
+
+/// Contours the face between `nodes`, ordered along `axis`
+fn face_proc<'a>(nodes: [Option<&'a Node>; 2], axis: usize, emit: &mut impl FnMut([&'a Leaf; 3])) {
+    let [Some(n0), Some(n1)] = nodes else {
+        return;
+    };
+    if matches!((n0, n1), (Node::Leaf(_), Node::Leaf(_))) {
+        return;
+    }
+
+    for (c0, c1, face_axis) in FACE_PROC_FACES[axis] {
+        face_proc([child(n0, c0), child(n1, c1)], face_axis, emit);
+    }
+
+    for (order, c, edge_axis) in FACE_PROC_EDGES[axis] {
+        let order = FACE_PROC_ORDERS[order];
+        let edge_nodes = [0, 1, 2, 3].map(|j| child([n0, n1][order[j]], c[j]));
+        edge_proc(edge_nodes, edge_axis, emit);
+    }
+}
+
+/// Contours the edge shared by `nodes`, which lies along `axis`
+fn edge_proc<'a>(nodes: [Option<&'a Node>; 4], axis: usize, emit: &mut impl FnMut([&'a Leaf; 3])) {
+    let [Some(n0), Some(n1), Some(n2), Some(n3)] = nodes else {
+        return;
+    };
+    let nodes = [n0, n1, n2, n3];
+
+    if let [Node::Leaf(l0), Node::Leaf(l1), Node::Leaf(l2), Node::Leaf(l3)] = nodes {
+        process_edge([l0, l1, l2, l3], axis, emit);
+        return;
+    }
+
+    for c in EDGE_PROC_EDGES[axis] {
+        edge_proc([0, 1, 2, 3].map(|j| child(nodes[j], c[j])), axis, emit);
+    }
+}
+
+/// Emits the quad dual to the edge shared by `leaves`, if the surface crosses it
+fn process_edge<'a>(leaves: [&'a Leaf; 4], axis: usize, emit: &mut impl FnMut([&'a Leaf; 3])) {
+    // The smallest leaf holds the actual (shortest) edge
+    let (i, smallest) = leaves
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, leaf)| leaf.size)
+        .unwrap();
+
+    let (c0, c1) = EDGE_CORNERS[PROCESS_EDGES[axis][i]];
+    if smallest.inside(c0) == smallest.inside(c1) {
+        return;
+    }
+
+    let [l0, l1, l2, l3] = leaves;
+    if smallest.inside(c0) {
+        emit([l0, l3, l1]);
+        emit([l0, l2, l3]);
+    } else {
+        emit([l0, l1, l3]);
+        emit([l0, l3, l2]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+    use crate::dual_contouring;
+    use crate::test_util::{boundary_edges, sphere};
+
+    #[test]
+    fn zero_error_matches_uniform_grid() {
+        let field = sphere([17; 3], Vec3::splat(8.0), 5.3);
+        let mesh = dual_contouring_octree(&field, 0.0, 0.0);
+        assert_eq!(mesh.num_triangles(), dual_contouring(&field, 0.0).num_triangles());
+    }
+
+    #[test]
+    fn collapsing_reduces_faces() {
+        let field = sphere([17; 3], Vec3::splat(8.0), 6.3);
+        let uniform = dual_contouring_octree(&field, 0.0, 0.0).num_triangles();
+        let collapsed = dual_contouring_octree(&field, 0.0, 0.1).num_triangles();
+        assert!(collapsed > 0 && collapsed < uniform, "{collapsed} of {uniform}");
+    }
+
+    #[test]
+    fn collapsed_mesh_is_closed() {
+        let field = sphere([17; 3], Vec3::splat(8.0), 6.3);
+        for max_error in [0.0, 0.01, 0.1] {
+            let mesh = dual_contouring_octree(&field, 0.0, max_error);
+            assert!(!mesh.is_empty());
+            assert_eq!(boundary_edges(&mesh), 0, "{max_error}");
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use crate::field::ScalarField;
+use crate::geometry::GridGeometry;
+use crate::marching_cubes::{MarchingCubesMode, NormalMode};
+use crate::mesh::Mesh;
+use crate::qef::QefOptions;
+
+/// Settings shared by the extractors taking options, e.g.
+/// [`crate::marching_cubes_with`] and [`crate::dual_contouring_with`]
+///
+/// The defaults give the same meshes as the functions without options at an
+/// iso level of 0. Settings an extractor has no use for are ignored by it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshingOptions {
+    /// Density of the surface, samples at or below it are inside
+    pub iso_level: f32,
+    /// Placement of dual contouring vertices in their cells
+    pub qef: QefOptions,
+    /// Handling of ambiguous cells by marching cubes
+    pub mode: MarchingCubesMode,
+    /// Vertex normals of marching cubes
+    pub normals: NormalMode,
+    /// Placement of the grid in space, or `None` to scale it into [0, 1), see
+    /// [`GridGeometry::normalized`]
+    ///
+    /// The chunked extractors, e.g. [`crate::marching_cubes_chunk_with`],
+    /// output grid coordinates of the whole field when `None`.
+    pub geometry: Option<GridGeometry>,
+    /// Output triangles in clockwise order, for renderers treating those as
+    /// front facing, see [`Mesh::flip_winding`]
+    pub flip_winding: bool,
+    /// Remove triangles with zero area, see [`Mesh::remove_degenerate`]
+    ///
+    /// The number removed is reported in [`Mesh::removed_degenerate`]. Left
+    /// in, they get an arbitrary unit normal.
+    pub remove_degenerate: bool,
+}
+
+impl Default for MeshingOptions {
+    fn default() -> Self {
+        Self {
+            iso_level: 0.0,
+            qef: QefOptions::default(),
+            mode: MarchingCubesMode::default(),
+            normals: NormalMode::default(),
+            geometry: None,
+            flip_winding: false,
+            remove_degenerate: false,
+        }
+    }
+}
+
+impl MeshingOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn iso_level(mut self, iso_level: f32) -> Self {
+        self.iso_level = iso_level;
+        self
+    }
+
+    pub fn qef(mut self, qef: QefOptions) -> Self {
+        self.qef = qef;
+        self
+    }
+
+    /// Sets [`QefOptions::cutoff`]
+    pub fn cutoff(mut self, cutoff: f32) -> Self {
+        self.qef.cutoff = cutoff;
+        self
+    }
+
+    /// Sets [`QefOptions::bias_strength`]
+    pub fn bias_strength(mut self, bias_strength: f32) -> Self {
+        self.qef.bias_strength = bias_strength;
+        self
+    }
+
+    /// Sets [`QefOptions::constrained`]
+    pub fn constrained(mut self, constrained: bool) -> Self {
+        self.qef.constrained = constrained;
+        self
+    }
+
+    /// Sets [`QefOptions::margin`]
+    pub fn margin(mut self, margin: f32) -> Self {
+        self.qef.margin = margin;
+        self
+    }
+
+    pub fn mode(mut self, mode: MarchingCubesMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn normals(mut self, normals: NormalMode) -> Self {
+        self.normals = normals;
+        self
+    }
+
+    pub fn geometry(mut self, geometry: GridGeometry) -> Self {
+        self.geometry = Some(geometry);
+        self
+    }
+
+    pub fn flip_winding(mut self, flip_winding: bool) -> Self {
+        self.flip_winding = flip_winding;
+        self
+    }
+
+    pub fn remove_degenerate(mut self, remove_degenerate: bool) -> Self {
+        self.remove_degenerate = remove_degenerate;
+        self
+    }
+
+    /// Geometry of the grid of `field`
+    pub(crate) fn grid<F: ScalarField + ?Sized>(&self, field: &F) -> GridGeometry {
+        self.geometry.unwrap_or_else(|| GridGeometry::normalized(field))
+    }
+
+    /// Applies the post-processing settings to `mesh`, extracted with the
+    /// spacing of `grid` applied
+    pub(crate) fn finish(&self, mut mesh: Mesh, grid: &GridGeometry) -> Mesh {
+        if self.remove_degenerate {
+            mesh.remove_degenerate();
+        }
+        if let Some(placement) = grid.placement() {
+            mesh.transform(placement);
+        }
+        if self.flip_winding {
+            mesh.flip_winding();
+        }
+        mesh
+    }
+}
@@ -0,0 +1,29 @@
+use std::ops::Range;
+
+/// Number of cell layers along z processed as one unit of work
+pub(crate) const SLAB_DEPTH: usize = 8;
+
+/// Calls `f` with the cell layers of each slab and returns the results in slab order
+///
+/// Slabs are processed in parallel if the `rayon` feature is enabled. The
+/// partition only depends on `num_layers`, so the results are the same
+/// regardless of the number of threads.
+pub(crate) fn map_slabs<T, F>(num_layers: usize, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(Range<usize>) -> T + Sync + Send,
+{
+    let num_slabs = num_layers.div_ceil(SLAB_DEPTH);
+    let slab = |i: usize| f(i * SLAB_DEPTH..((i + 1) * SLAB_DEPTH).min(num_layers));
+
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        (0..num_slabs).into_par_iter().map(slab).collect()
+    }
+
+    #[cfg(not(feature = "rayon"))]
+    {
+        (0..num_slabs).map(slab).collect()
+    }
+}
@@ -0,0 +1,420 @@
+use glam::{DVec3, Vec3};
+
+/// How a vertex is placed within its cell from a [`Qef`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QefOptions {
+    /// Singular value cutoff, see [`Qef::solve`]
+    pub cutoff: f32,
+    /// Weight of the squared distance to the mass point added to the error
+    ///
+    /// Pulls the vertex towards the mass point, the same as adding three
+    /// planes through it with normals of this length along the axes. Zero
+    /// disables it. Defaults to 1, which keeps the vertices of cells with
+    /// few crossings from drifting to the far side of the cell.
+    pub bias_strength: f32,
+    /// Minimize the error within the cell, rather than clamping the
+    /// unconstrained minimizer to it
+    ///
+    /// Clamping each axis separately moves the vertex off the planes it
+    /// should be on, while the constrained minimizer is the best point within
+    /// the cell.
+    pub constrained: bool,
+    /// Distance the vertex may be outside its cell, relative to the cell size
+    pub margin: f32,
+}
+
+impl Default for QefOptions {
+    fn default() -> Self {
+        Self {
+            cutoff: Qef::DEFAULT_CUTOFF,
+            bias_strength: 1.0,
+            constrained: false,
+            margin: 0.0,
+        }
+    }
+}
+
+/// Quadratic error function, the sum of the squared distances from a point to
+/// a set of planes
+///
+/// The planes are the rows `n . p = n . q` of a system `A p = b`, through the
+/// points `q` with normals `n` passed to [`Qef::add`]. Only `AᵀA`, `Aᵀb` and
+/// `bᵀb` are stored, so a QEF has the same size whatever the number of planes
+/// and two of them can be merged by adding their terms. The terms are kept in
+/// double precision, as the error is the difference of large values far from
+/// the origin.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Qef {
+    /// Upper triangle of the symmetric matrix `AᵀA`, as xx, xy, xz, yy, yz, zz
+    ata: [f64; 6],
+    atb: DVec3,
+    btb: f64,
+    /// Sum of the points the planes pass through
+    point_sum: DVec3,
+    num_points: u32,
+}
+
+impl Qef {
+    /// Singular values of `A` below this fraction of the largest are ignored
+    /// by [`Qef::solve`] unless told otherwise
+    pub const DEFAULT_CUTOFF: f32 = 0.1;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the plane through `point` with normal `normal`
+    ///
+    /// `normal` is expected to be of unit length. The point counts towards the
+    /// mass point even if `normal` is zero.
+    pub fn add(&mut self, point: Vec3, normal: Vec3) {
+        let (q, n) = (point.as_dvec3(), normal.as_dvec3());
+        let d = n.dot(q);
+
+        self.ata[0] += n.x * n.x;
+        self.ata[1] += n.x * n.y;
+        self.ata[2] += n.x * n.z;
+        self.ata[3] += n.y * n.y;
+        self.ata[4] += n.y * n.z;
+        self.ata[5] += n.z * n.z;
+        self.atb += n * d;
+        self.btb += d * d;
+
+        self.point_sum += q;
+        self.num_points += 1;
+    }
+
+    /// Adds the planes of `other`
+    pub fn merge(&mut self, other: &Qef) {
+        for (a, b) in self.ata.iter_mut().zip(other.ata) {
+            *a += b;
+        }
+        self.atb += other.atb;
+        self.btb += other.btb;
+        self.point_sum += other.point_sum;
+        self.num_points += other.num_points;
+    }
+
+    /// Number of planes added
+    pub fn num_points(&self) -> u32 {
+        self.num_points
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_points == 0
+    }
+
+    /// Average of the points the planes pass through, the origin if there are
+    /// none
+    pub fn mass_point(&self) -> Vec3 {
+        self.dmass_point().as_vec3()
+    }
+
+    fn dmass_point(&self) -> DVec3 {
+        self.point_sum / self.num_points.max(1) as f64
+    }
+
+    fn matrix(&self) -> [[f64; 3]; 3] {
+        let a = &self.ata;
+        [
+            [a[0], a[1], a[2]],
+            [a[1], a[3], a[4]],
+            [a[2], a[4], a[5]],
+        ]
+    }
+
+    /// Error at `p`, the sum of the squared distances to the planes
+    pub fn error(&self, p: Vec3) -> f32 {
+        self.derror(p.as_dvec3()) as f32
+    }
+
+    fn derror(&self, p: DVec3) -> f64 {
+        // Rounding may take it slightly below zero
+        (p.dot(mul(&self.matrix(), p)) - 2.0 * p.dot(self.atb) + self.btb).max(0.0)
+    }
+
+    /// Point minimizing the error and the error there
+    ///
+    /// The system is solved relative to the mass point with the pseudo-inverse
+    /// of `AᵀA`, from its eigendecomposition. Directions whose singular value
+    /// is below `cutoff` times the largest one are left out, so flat or
+    /// edge-like sets of planes, whose `AᵀA` is singular or nearly so, are
+    /// solved for the point closest to the mass point rather than failing or
+    /// landing far away.
+    pub fn solve(&self, cutoff: f32) -> (Vec3, f32) {
+        let vertex = self.minimize(cutoff as f64, 0.0, [None; 3]).as_vec3();
+        (vertex, self.error(vertex))
+    }
+
+    /// Vertex for the cell from `min` to `max` and its error, placed as set
+    /// by `options`
+    pub fn solve_in_cell(&self, min: Vec3, max: Vec3, options: &QefOptions) -> (Vec3, f32) {
+        let margin = (max - min) * options.margin;
+        let (min, max) = ((min - margin).as_dvec3(), (max + margin).as_dvec3());
+        let cutoff = options.cutoff as f64;
+        let bias = (options.bias_strength as f64).powi(2);
+
+        let unconstrained = self.minimize(cutoff, bias, [None; 3]);
+        let vertex = if !options.constrained || inside(unconstrained, min, max) {
+            unconstrained.clamp(min, max)
+        } else {
+            // The minimizer within the box is the minimizer within one of its
+            // faces, edges or corners, with the other coordinates free
+            let mass_point = self.dmass_point();
+            let objective = |p: DVec3| self.derror(p) + bias * p.distance_squared(mass_point);
+
+            let mut best = (f64::INFINITY, min);
+            for face in 1..27 {
+                let mut fixed = [None; 3];
+                let mut code = face;
+                for (i, f) in fixed.iter_mut().enumerate() {
+                    *f = match code % 3 {
+                        0 => None,
+                        1 => Some(min[i]),
+                        _ => Some(max[i]),
+                    };
+                    code /= 3;
+                }
+
+                let p = self.minimize(cutoff, bias, fixed);
+                if inside(p, min, max) && objective(p) < best.0 {
+                    best = (objective(p), p);
+                }
+            }
+            best.1.clamp(min, max)
+        };
+
+        let vertex = vertex.as_vec3();
+        (vertex, self.error(vertex))
+    }
+
+    /// Minimizer of the error plus `bias` times the squared distance to the
+    /// mass point, with the coordinates in `fixed` held at the given values
+    fn minimize(&self, cutoff: f64, bias: f64, fixed: [Option<f64>; 3]) -> DVec3 {
+        let mass_point = self.dmass_point();
+        let mut m = self.matrix();
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] += bias;
+        }
+
+        // Offset from the mass point, the fixed part of it is known
+        let mut offset = DVec3::ZERO;
+        for (i, f) in fixed.iter().enumerate() {
+            if let Some(value) = f {
+                offset[i] = value - mass_point[i];
+            }
+        }
+        let mut residual = self.atb - mul(&self.matrix(), mass_point) - mul(&m, offset);
+
+        // Drop the fixed coordinates from the system, which leaves them with
+        // zero eigenvalues
+        for (i, f) in fixed.iter().enumerate() {
+            if f.is_some() {
+                m[i] = [0.0; 3];
+                for row in &mut m {
+                    row[i] = 0.0;
+                }
+                residual[i] = 0.0;
+            }
+        }
+
+        let (eigenvalues, eigenvectors) = symmetric_eigen(m);
+        let max = eigenvalues.iter().fold(0.0_f64, |a, &b| a.max(b));
+
+        // Eigenvalues of AᵀA are the squared singular values of A
+        let cutoff = cutoff.powi(2) * max;
+        for (&eigenvalue, v) in eigenvalues.iter().zip(eigenvectors) {
+            if eigenvalue > 0.0 && eigenvalue >= cutoff {
+                offset += v * (v.dot(residual) / eigenvalue);
+            }
+        }
+
+        mass_point + offset
+    }
+}
+
+fn mul(m: &[[f64; 3]; 3], v: DVec3) -> DVec3 {
+    DVec3::new(
+        DVec3::from(m[0]).dot(v),
+        DVec3::from(m[1]).dot(v),
+        DVec3::from(m[2]).dot(v),
+    )
+}
+
+/// Whether `p` is within the box from `min` to `max`, up to rounding
+fn inside(p: DVec3, min: DVec3, max: DVec3) -> bool {
+    let tolerance = 1e-9 * (max - min).max_element().max(1.0);
+    p.cmpge(min - tolerance).all() && p.cmple(max + tolerance).all()
+}
+
+/// Eigenvalues and unit eigenvectors of the symmetric matrix `m`, with the
+/// cyclic Jacobi method
+fn symmetric_eigen(mut m: [[f64; 3]; 3]) -> ([f64; 3], [DVec3; 3]) {
+    const MAX_SWEEPS: usize = 16;
+
+    // Columns are the eigenvectors
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+    for _ in 0..MAX_SWEEPS {
+        let off_diagonal = m[0][1].powi(2) + m[0][2].powi(2) + m[1][2].powi(2);
+        let diagonal = m[0][0].powi(2) + m[1][1].powi(2) + m[2][2].powi(2);
+        if off_diagonal <= f64::EPSILON * f64::EPSILON * diagonal {
+            break;
+        }
+
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if m[p][q] == 0.0 {
+                continue;
+            }
+
+            // Rotation in the (p, q) plane zeroing m[p][q]
+            let theta = (m[q][q] - m[p][p]) / (2.0 * m[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+
+            for row in m.iter_mut().chain(v.iter_mut()) {
+                let (a, b) = (row[p], row[q]);
+                row[p] = c * a - s * b;
+                row[q] = s * a + c * b;
+            }
+            let (a, b) = (m[p], m[q]);
+            for k in 0..3 {
+                m[p][k] = c * a[k] - s * b[k];
+                m[q][k] = s * a[k] + c * b[k];
+            }
+        }
+    }
+
+    let eigenvectors = [0, 1, 2].map(|i| DVec3::new(v[0][i], v[1][i], v[2][i]));
+    ([m[0][0], m[1][1], m[2][2]], eigenvectors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!(a.distance(b) < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn corner_of_three_planes() {
+        let mut qef = Qef::new();
+        qef.add(Vec3::new(0.3, 0.1, 0.9), Vec3::X);
+        qef.add(Vec3::new(0.5, 0.6, 0.0), Vec3::Y);
+        qef.add(Vec3::new(1.0, 0.2, 0.2), Vec3::Z);
+
+        let (vertex, error) = qef.solve(Qef::DEFAULT_CUTOFF);
+        assert_near(vertex, Vec3::new(0.3, 0.6, 0.2));
+        assert!(error < 1e-9);
+    }
+
+    #[test]
+    fn tilted_corner() {
+        let corner = Vec3::new(0.4, 0.7, 0.5);
+        let normals = [Vec3::new(1.0, 1.0, 0.0), Vec3::new(-1.0, 1.0, 1.0), Vec3::new(0.0, -1.0, 2.0)];
+
+        let mut qef = Qef::new();
+        for (i, n) in normals.into_iter().enumerate() {
+            let n = n.normalize();
+            // Another point of the plane
+            let p = corner + n.any_orthogonal_vector() * (0.1 + 0.2 * i as f32);
+            qef.add(p, n);
+        }
+        assert_near(qef.solve(Qef::DEFAULT_CUTOFF).0, corner);
+    }
+
+    #[test]
+    fn plane_falls_back_to_mass_point() {
+        // Rank 1, the vertex is the mass point moved onto the plane
+        let mut qef = Qef::new();
+        for p in [Vec3::new(0.1, 0.2, 0.5), Vec3::new(0.9, 0.4, 0.5), Vec3::new(0.5, 0.9, 0.5)] {
+            qef.add(p, Vec3::Z);
+        }
+        qef.add(Vec3::new(0.3, 0.5, 0.6), Vec3::Z);
+
+        let (vertex, error) = qef.solve(Qef::DEFAULT_CUTOFF);
+        assert_near(vertex, Vec3::new(0.45, 0.5, 0.525));
+        assert!((error - (3.0 * 0.025_f32.powi(2) + 0.075_f32.powi(2))).abs() < 1e-6);
+    }
+
+    #[test]
+    fn edge_falls_back_to_mass_point() {
+        // Rank 2, the vertex is the point of the edge closest to the mass
+        // point
+        let mut qef = Qef::new();
+        qef.add(Vec3::new(0.3, 0.0, 0.2), Vec3::X);
+        qef.add(Vec3::new(0.3, 1.0, 0.4), Vec3::X);
+        qef.add(Vec3::new(0.0, 0.6, 0.6), Vec3::Y);
+        qef.add(Vec3::new(1.0, 0.6, 0.8), Vec3::Y);
+
+        let (vertex, error) = qef.solve(Qef::DEFAULT_CUTOFF);
+        assert_near(vertex, Vec3::new(0.3, 0.6, 0.5));
+        assert!(error < 1e-9);
+    }
+
+    #[test]
+    fn nearly_parallel_planes_are_truncated() {
+        // The planes meet at x = 2.8, far outside the cell. The small
+        // singular value is dropped, leaving the vertex near the mass point.
+        let mut qef = Qef::new();
+        qef.add(Vec3::new(0.2, 0.5, 0.5), Vec3::Z);
+        qef.add(Vec3::new(0.8, 0.5, 0.52), Vec3::new(0.01, 0.0, 1.0).normalize());
+
+        let (vertex, _) = qef.solve(Qef::DEFAULT_CUTOFF);
+        assert!(vertex.distance(Vec3::new(0.5, 0.5, 0.51)) < 0.01, "{vertex}");
+
+        // Without the cutoff it is solved exactly
+        let (vertex, _) = qef.solve(0.0);
+        assert!((vertex.x - 2.8).abs() < 1e-3, "{vertex}");
+    }
+
+    #[test]
+    fn bias_pulls_towards_mass_point() {
+        let mut qef = Qef::new();
+        qef.add(Vec3::new(0.3, 0.1, 0.9), Vec3::X);
+        qef.add(Vec3::new(0.5, 0.6, 0.0), Vec3::Y);
+        qef.add(Vec3::new(1.0, 0.2, 0.2), Vec3::Z);
+        let corner = Vec3::new(0.3, 0.6, 0.2);
+
+        let options = |bias_strength| QefOptions { bias_strength, ..Default::default() };
+        let (unbiased, _) = qef.solve_in_cell(Vec3::ZERO, Vec3::ONE, &options(0.0));
+        assert_near(unbiased, corner);
+
+        // Each coordinate is the average of the plane and the mass point
+        let (biased, _) = qef.solve_in_cell(Vec3::ZERO, Vec3::ONE, &options(1.0));
+        assert_near(biased, (corner + qef.mass_point()) / 2.0);
+    }
+
+    #[test]
+    fn constrained_vertex_stays_in_cell() {
+        // Two planes meeting at (1.5, 0.25, z), outside the cell
+        let mut qef = Qef::new();
+        let normals = [Vec3::new(1.0, 1.0, 0.0).normalize(), Vec3::new(1.0, -3.0, 0.0).normalize()];
+        for n in normals {
+            let corner = Vec3::new(1.5, 0.25, 0.5);
+            qef.add(corner - n.cross(Vec3::Z) * 0.8, n);
+        }
+
+        let (min, max) = (Vec3::ZERO, Vec3::ONE);
+        let options = QefOptions { bias_strength: 0.0, constrained: true, ..Default::default() };
+        let (constrained, error) = qef.solve_in_cell(min, max, &options);
+        let (clamped, clamped_error) = qef.solve_in_cell(min, max, &QefOptions { constrained: false, ..options });
+
+        assert!(constrained.cmpge(min).all() && constrained.cmple(max).all(), "{constrained}");
+        assert!(error < clamped_error, "{error} >= {clamped_error}");
+        assert_near(clamped, Vec3::new(1.0, 0.25, 0.5));
+
+        // A margin lets it leave the cell, but no further
+        let margin = QefOptions { margin: 0.1, ..options };
+        let (vertex, _) = qef.solve_in_cell(min, max, &margin);
+        assert!(vertex.x <= 1.1 + 1e-6 && vertex.x > 1.0, "{vertex}");
+    }
+
+    #[test]
+    fn empty_qef_solves_to_origin() {
+        let (vertex, error) = Qef::new().solve(Qef::DEFAULT_CUTOFF);
+        assert_eq!((vertex, error), (Vec3::ZERO, 0.0));
+    }
+}
@@ -0,0 +1,305 @@
+use glam::{Affine3A, Mat3, Vec3};
+
+use crate::degenerate::{degenerate, unit_normal};
+use crate::dual_contouring::{
+    layer_quads, layer_vertices, qef_vertex, quad_cells, quad_normals, ALL_CELLS, NO_VERTEX,
+};
+use crate::field::{index, ScalarField};
+use crate::geometry::GridGeometry;
+use crate::marching_cubes::{IndexedBuffers, MarchingCubesMode};
+use crate::{marching_cubes, marching_cubes_33};
+use crate::mesh::Mesh;
+use crate::options::MeshingOptions;
+
+/// Receiver of the vertices and triangles of an indexed mesh, e.g. a GPU
+/// staging buffer or a pooled allocation
+///
+/// All vertices of a mesh are pushed before its triangles.
+pub trait MeshSink {
+    /// Adds a vertex, returning the index triangles refer to it by
+    fn push_vertex(&mut self, position: [f32;3], normal: [f32;3]) -> u32;
+    /// Adds a counter-clockwise triangle, or clockwise if
+    /// [`MeshingOptions::flip_winding`] is set
+    fn push_triangle(&mut self, indices: [u32; 3]);
+
+    /// Called once the triangles are pushed with the number of triangles
+    /// with zero area left out, see [`MeshingOptions::remove_degenerate`]
+    fn removed_degenerate(&mut self, _count: usize) {}
+}
+
+/// Appends to the mesh as an indexed triangle mesh, triangulating quads and
+/// indexing a soup first
+impl MeshSink for Mesh {
+    fn push_vertex(&mut self, position: [f32;3], normal: [f32;3]) -> u32 {
+        self.triangulate();
+        if self.indices.is_none() {
+            self.indices = Some((0..self.positions.len() as u32).collect());
+        }
+
+        self.positions.push(position);
+        self.normals.push(normal);
+        (self.positions.len() - 1) as u32
+    }
+
+    fn push_triangle(&mut self, indices: [u32; 3]) {
+        self.indices.get_or_insert_with(Vec::new).extend_from_slice(&indices);
+    }
+
+    fn removed_degenerate(&mut self, count: usize) {
+        self.removed_degenerate += count;
+    }
+}
+
+/// Buffers kept between calls to [`marching_cubes_into`] and
+/// [`dual_contouring_into`], so that meshing fields of similar size again
+/// does not allocate
+///
+/// Dual contouring maps cells to vertices two layers at a time, rather than
+/// for the whole field, but buffers all vertices and quads, see
+/// [`dual_contouring_into`].
+#[derive(Default)]
+pub struct MeshingScratch {
+    indexed: IndexedBuffers,
+    cell_to_vertex: Vec<u32>,
+    vertices: Vec<Vec3>,
+    quads: Vec<[u32; 4]>,
+    normals: Vec<Vec3>,
+    /// Local vertex index -> index returned by the sink
+    sink_indices: Vec<u32>,
+}
+
+impl MeshingScratch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// [`crate::marching_cubes_indexed_with`], pushing the mesh into `sink`
+///
+/// Meshing runs on the calling thread. Fields with fewer than two samples
+/// along an axis give no triangles. With
+/// [`MeshingOptions::remove_degenerate`] set, triangles with zero area are
+/// skipped, but unlike [`Mesh::remove_degenerate`] their vertices are neither
+/// welded nor removed.
+pub fn marching_cubes_into<F, S>(
+    field: &F,
+    options: MeshingOptions,
+    scratch: &mut MeshingScratch,
+    sink: &mut S,
+)
+where
+    F: ScalarField + ?Sized,
+    S: MeshSink + ?Sized,
+{
+    if field.dims().iter().any(|&n| n < 2) {
+        return;
+    }
+
+    let grid = options.grid(field);
+    let mut emitter = Emitter::new(&options, &grid);
+    let (iso_level, normals, scale) = (options.iso_level, options.normals, grid.spacing);
+    let extract_layers = match options.mode {
+        MarchingCubesMode::Classic => marching_cubes::extract_layers,
+        MarchingCubesMode::Mc33 => marching_cubes_33::extract_layers,
+    };
+
+    let buffers = &mut scratch.indexed;
+    buffers.clear();
+    extract_layers(field, iso_level, normals, [0, 0, 0], scale, 0..field.dims()[2] - 1, buffers);
+
+    emitter.vertices(
+        sink,
+        buffers.positions.iter().zip(&buffers.normals)
+            .map(|(&p, &n)| (p.into(), unit_normal(n))),
+        &mut scratch.sink_indices,
+    );
+    for t in buffers.indices.chunks_exact(3) {
+        let t = [t[0], t[1], t[2]];
+        emitter.triangle(sink, t.map(|v| buffers.positions[v as usize].into()), t, &scratch.sink_indices);
+    }
+    emitter.finish(sink);
+}
+
+/// [`crate::dual_contouring_indexed_with`], pushing the mesh into `sink`
+///
+/// Meshing runs on the calling thread. Quads are split into two triangles,
+/// and small fields and [`MeshingOptions::remove_degenerate`] are handled as
+/// by [`marching_cubes_into`].
+///
+/// Only the map from cells to vertices is kept for two layers at a time. The
+/// vertices and quads of the whole field are collected in `scratch` before
+/// anything is pushed, since the sink takes all vertices before the triangles
+/// and each vertex normal averages the quads around it.
+pub fn dual_contouring_into<F, S>(
+    field: &F,
+    options: MeshingOptions,
+    scratch: &mut MeshingScratch,
+    sink: &mut S,
+)
+where
+    F: ScalarField + ?Sized,
+    S: MeshSink + ?Sized,
+{
+    if field.dims().iter().any(|&n| n < 2) {
+        return;
+    }
+
+    let [width, height, _] = field.dims();
+    let grid = options.grid(field);
+    let mut emitter = Emitter::new(&options, &grid);
+    let iso_level = options.iso_level;
+    let place = |cell| qef_vertex(field, iso_level, cell, &options.qef);
+
+    let MeshingScratch { cell_to_vertex, vertices, quads, normals, sink_indices, .. } = scratch;
+    vertices.clear();
+    quads.clear();
+
+    // Cell -> vertex maps of two consecutive layers, layer z in slot z % 2.
+    // The quads of a layer use the vertices of the next one.
+    let layer_size = width * height;
+    cell_to_vertex.clear();
+    cell_to_vertex.resize(2 * layer_size, NO_VERTEX);
+    let fill_layer = |z: usize, cell_to_vertex: &mut [u32], vertices: &mut Vec<Vec3>| {
+        let slot = &mut cell_to_vertex[(z % 2) * layer_size..][..layer_size];
+        slot.fill(NO_VERTEX);
+        layer_vertices(field, iso_level, [0, 0, 0], grid.spacing, &place, z..z + 1, vertices, slot);
+    };
+
+    let num_layers = quad_cells(field.dims(), ALL_CELLS)[2];
+    fill_layer(0, cell_to_vertex, vertices);
+    for z in 0..num_layers {
+        if z + 1 < num_layers {
+            fill_layer(z + 1, cell_to_vertex, vertices);
+        }
+
+        let ring = &cell_to_vertex[..];
+        let vertex = |[x, y, z]: [usize; 3], _| ring[(z % 2) * layer_size + index(x, y, 0, width, height)];
+        layer_quads(field, iso_level, ALL_CELLS, &vertex, z..z + 1, quads);
+    }
+
+    quad_normals(vertices, quads, normals);
+    emitter.vertices(
+        sink,
+        vertices.iter().zip(normals.iter()).map(|(&p, &n)| (p, unit_normal(n))),
+        sink_indices,
+    );
+    for &[v0, v1, v2, v3] in quads.iter() {
+        for t in [[v0, v1, v2], [v0, v2, v3]] {
+            emitter.triangle(sink, t.map(|v| vertices[v as usize]), t, sink_indices);
+        }
+    }
+    emitter.finish(sink);
+}
+
+/// Applies the post-processing settings of [`MeshingOptions::finish`] while
+/// pushing a mesh into a sink
+struct Emitter {
+    placement: Option<Affine3A>,
+    /// Inverse transpose of the placement, see [`Mesh::transform`]
+    normal_matrix: Mat3,
+    flip_winding: bool,
+    remove_degenerate: bool,
+    /// Number of triangles left out for having zero area
+    num_removed: usize,
+}
+
+impl Emitter {
+    fn new(options: &MeshingOptions, grid: &GridGeometry) -> Self {
+        let placement = grid.placement();
+        let matrix = placement.map_or(Mat3::IDENTITY, |p| p.matrix3.into());
+        Self {
+            placement,
+            normal_matrix: matrix.inverse().transpose(),
+            // Mirroring placements flip the winding as well
+            flip_winding: options.flip_winding != (matrix.determinant() < 0.0),
+            remove_degenerate: options.remove_degenerate,
+            num_removed: 0,
+        }
+    }
+
+    /// Pushes the vertices, given with normalized normals, recording the
+    /// index the sink gave each in `sink_indices`
+    fn vertices<S: MeshSink + ?Sized>(
+        &self,
+        sink: &mut S,
+        vertices: impl Iterator<Item = (Vec3, Vec3)>,
+        sink_indices: &mut Vec<u32>,
+    ) {
+        sink_indices.clear();
+        for (position, normal) in vertices {
+            let (position, normal) = match self.placement {
+                Some(placement) => (
+                    placement.transform_point3(position),
+                    unit_normal(self.normal_matrix * normal),
+                ),
+                None => (position, normal),
+            };
+            sink_indices.push(sink.push_vertex(position.into(), normal.into()));
+        }
+    }
+
+    /// Pushes the triangle with the given positions, before placement, and
+    /// local vertex indices
+    fn triangle<S: MeshSink + ?Sized>(
+        &mut self,
+        sink: &mut S,
+        [p0, p1, p2]: [Vec3; 3],
+        [v0, v1, v2]: [u32; 3],
+        sink_indices: &[u32],
+    ) {
+        if self.remove_degenerate && degenerate(p0, p1, p2) {
+            self.num_removed += 1;
+            return;
+        }
+
+        let [i0, i1, i2] = [v0, v1, v2].map(|v| sink_indices[v as usize]);
+        if self.flip_winding {
+            sink.push_triangle([i0, i2, i1]);
+        } else {
+            sink.push_triangle([i0, i1, i2]);
+        }
+    }
+
+    /// Reports the triangles left out to the sink
+    fn finish<S: MeshSink + ?Sized>(self, sink: &mut S) {
+        sink.removed_degenerate(self.num_removed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+    use crate::test_util::{noise, sphere, FnField};
+    use crate::marching_cubes_indexed_with;
+
+    #[test]
+    fn mc33_matches_indexed_mesh() {
+        let field = FnField { dims: [10, 10, 10], f: |p: [usize; 3]| noise(p, 1) };
+        let options = MeshingOptions::new().mode(MarchingCubesMode::Mc33);
+        let expected = marching_cubes_indexed_with(&field, options);
+
+        let mut scratch = MeshingScratch::new();
+        for _ in 0..2 {
+            let mut mesh = Mesh::default();
+            marching_cubes_into(&field, options, &mut scratch, &mut mesh);
+            assert_eq!(mesh.indices, expected.indices);
+            for (p, q) in mesh.positions.iter().zip(&expected.positions) {
+                assert!((Vec3::from(*p) - Vec3::from(*q)).length() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn small_fields_give_empty_meshes() {
+        let mut scratch = MeshingScratch::new();
+        for dims in [[1, 8, 8], [8, 8, 1], [0, 0, 0]] {
+            let field = sphere(dims, Vec3::splat(4.0), 3.0);
+            let mut mesh = Mesh::default();
+            marching_cubes_into(&field, MeshingOptions::new(), &mut scratch, &mut mesh);
+            dual_contouring_into(&field, MeshingOptions::new(), &mut scratch, &mut mesh);
+            assert!(mesh.positions.is_empty());
+        }
+    }
+}
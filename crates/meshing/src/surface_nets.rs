@@ -0,0 +1,136 @@
+use glam::Vec3;
+
+use crate::dual_contouring::{cell_vertex_quads, cell_vertices, indexed_mesh, soup_mesh, ALL_CELLS, CORNERS};
+use crate::field::{normalize_scale, ScalarField};
+use crate::mesh::Mesh;
+use crate::options::MeshingOptions;
+
+/// Places the vertex of cell (x, y, z) at the average of the crossings on its edges
+fn mass_point<F: ScalarField + ?Sized>(field: &F, iso_level: f32, [x, y, z]: [usize; 3]) -> Option<Vec3> {
+    let corner = |c: usize| Vec3::new(CORNERS[c].0 as f32, CORNERS[c].1 as f32, CORNERS[c].2 as f32);
+    let sample = |c: usize| field.sample_relative(x + CORNERS[c].0, y + CORNERS[c].1, z + CORNERS[c].2, iso_level);
+
+    let mut sum = Vec3::ZERO;
+    let mut num_crossings = 0;
+
+    // Corners are numbered 4x + 2y + z, so each edge joins a corner to one
+    // with a single additional bit set
+    for a in 0..8 {
+        for axis_bit in [1, 2, 4] {
+            if a & axis_bit != 0 {
+                continue;
+            }
+            let b = a | axis_bit;
+
+            let (v0, v1) = (sample(a), sample(b));
+            if (v0 > 0.0) != (v1 > 0.0) {
+                let t = -v0 / (v1 - v0);
+                sum += corner(a) + t * (corner(b) - corner(a));
+                num_crossings += 1;
+            }
+        }
+    }
+
+    if num_crossings == 0 {
+        return None;
+    }
+    Some(sum / num_crossings as f32)
+}
+
+/// Implements naive surface nets, S Gibson, Constrained Elastic Surface Nets,
+/// without the relaxation step
+///
+/// Like [`crate::dual_contouring`], but each vertex is placed at the average
+/// of the crossings on the edges of its cell. No normals are needed, which
+/// makes it much cheaper, and the result is smoother than marching cubes at
+/// the cost of rounding off sharp features.
+pub fn surface_nets<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+) -> Mesh {
+    let (vertices, cell_to_vertex) = net_vertices(field, iso_level, normalize_scale(field));
+    soup_mesh(&vertices, &cell_vertex_quads(field, iso_level, ALL_CELLS, &cell_to_vertex))
+}
+
+/// [`surface_nets`] with the given options, see
+/// [`crate::marching_cubes_with`]
+pub fn surface_nets_with<F: ScalarField + ?Sized>(
+    field: &F,
+    options: MeshingOptions,
+) -> Mesh {
+    let grid = options.grid(field);
+    let (vertices, cell_to_vertex) = net_vertices(field, options.iso_level, grid.spacing);
+    let mesh = soup_mesh(&vertices, &cell_vertex_quads(field, options.iso_level, ALL_CELLS, &cell_to_vertex));
+    options.finish(mesh, &grid)
+}
+
+/// Indexed version of [`surface_nets`], see [`crate::dual_contouring_indexed`]
+pub fn surface_nets_indexed<F: ScalarField + ?Sized>(
+    field: &F,
+    iso_level: f32,
+    quads: bool,
+) -> Mesh {
+    let (vertices, cell_to_vertex) = net_vertices(field, iso_level, normalize_scale(field));
+    indexed_mesh(vertices, &cell_vertex_quads(field, iso_level, ALL_CELLS, &cell_to_vertex), quads)
+}
+
+/// [`surface_nets_indexed`] with the given options, see
+/// [`crate::marching_cubes_with`]
+pub fn surface_nets_indexed_with<F: ScalarField + ?Sized>(
+    field: &F,
+    quads: bool,
+    options: MeshingOptions,
+) -> Mesh {
+    let grid = options.grid(field);
+    let (vertices, cell_to_vertex) = net_vertices(field, options.iso_level, grid.spacing);
+    let mesh = indexed_mesh(vertices, &cell_vertex_quads(field, options.iso_level, ALL_CELLS, &cell_to_vertex), quads);
+    options.finish(mesh, &grid)
+}
+
+/// Vertices of the cells scaled by `scale` and the cell -> vertex map, see
+/// [`cell_vertices`]
+fn net_vertices<F: ScalarField + ?Sized>(field: &F, iso_level: f32, scale: Vec3) -> (Vec<Vec3>, Vec<u32>) {
+    cell_vertices(field, iso_level, [0, 0, 0], scale, |cell| mass_point(field, iso_level, cell))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use glam::Vec3;
+
+    use super::*;
+    use crate::geometry::GridGeometry;
+    use crate::test_util::{boundary_edges, bordered_noise, edge_counts, signed_volume, sphere};
+
+    #[test]
+    fn sphere_is_closed_and_faces_outwards() {
+        let field = sphere([16; 3], Vec3::splat(7.5), 5.3);
+        let options = MeshingOptions::new().geometry(GridGeometry::default());
+        for mesh in [surface_nets_with(&field, options), surface_nets_indexed_with(&field, false, options)] {
+            assert!(edge_counts(&mesh).into_values().all(|n| n == 2));
+
+            let volume = 4.0 / 3.0 * PI * 5.3_f32.powi(3);
+            assert!((signed_volume(&mesh) / volume - 1.0).abs() < 0.05, "{}", signed_volume(&mesh));
+        }
+    }
+
+    #[test]
+    fn noise_is_closed() {
+        for seed in 0..4 {
+            let mesh = surface_nets_indexed(&bordered_noise([10; 3], seed), 0.0, true);
+            assert!(!mesh.is_empty());
+            assert_eq!(boundary_edges(&mesh), 0, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn indexed_mesh_has_the_soup_triangles() {
+        let field = bordered_noise([10; 3], 0);
+        let soup = surface_nets(&field, 0.0);
+        for quads in [false, true] {
+            let indexed = surface_nets_indexed(&field, 0.0, quads);
+            assert!(soup.triangles().eq(indexed.triangles()));
+        }
+    }
+}
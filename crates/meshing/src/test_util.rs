@@ -0,0 +1,164 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use glam::Vec3;
+
+use crate::cell_contour::CellShape;
+use crate::field::ScalarField;
+use crate::mesh::Mesh;
+
+/// Field evaluating `f` at the grid points
+pub(crate) struct FnField<F> {
+    pub(crate) dims: [usize; 3],
+    pub(crate) f: F,
+}
+
+impl<F: Fn([usize; 3]) -> f32 + Sync> ScalarField for FnField<F> {
+    fn dims(&self) -> [usize; 3] {
+        self.dims
+    }
+
+    fn sample(&self, x: usize, y: usize, z: usize) -> f32 {
+        (self.f)([x, y, z])
+    }
+}
+
+/// Signed distance to a sphere, negative inside
+pub(crate) fn sphere(dims: [usize; 3], center: Vec3, radius: f32) -> FnField<impl Fn([usize; 3]) -> f32 + Sync> {
+    FnField {
+        dims,
+        f: move |[x, y, z]: [usize; 3]| (Vec3::new(x as f32, y as f32, z as f32) - center).length() - radius,
+    }
+}
+
+/// Signed distance to an axis-aligned box, negative inside
+pub(crate) fn cuboid(dims: [usize; 3], center: Vec3, half_size: Vec3) -> FnField<impl Fn([usize; 3]) -> f32 + Sync> {
+    FnField {
+        dims,
+        f: move |[x, y, z]: [usize; 3]| {
+            let q = (Vec3::new(x as f32, y as f32, z as f32) - center).abs() - half_size;
+            q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+        },
+    }
+}
+
+/// Value in [-1, 1) hashed from grid point `p` and `seed`
+pub(crate) fn noise([x, y, z]: [usize; 3], seed: u32) -> f32 {
+    let mut h = seed.wrapping_mul(0x9e37_79b9) ^ (x as u32).wrapping_mul(0x85eb_ca6b);
+    h = (h ^ (y as u32).wrapping_mul(0xc2b2_ae35)).rotate_left(13);
+    h = (h ^ (z as u32).wrapping_mul(0x27d4_eb2f)).wrapping_mul(0x1656_67b1);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2c1b_3c6d);
+    h ^= h >> 12;
+    (h >> 8) as f32 / (1 << 23) as f32 - 1.0
+}
+
+/// [`noise`] inside a border of samples at 1, so that the surface is closed
+pub(crate) fn bordered_noise(dims: [usize; 3], seed: u32) -> FnField<impl Fn([usize; 3]) -> f32 + Sync> {
+    FnField {
+        dims,
+        f: move |p: [usize; 3]| {
+            if (0..3).any(|i| p[i] == 0 || p[i] + 1 == dims[i]) { 1.0 } else { noise(p, seed) }
+        },
+    }
+}
+
+/// Volume enclosed by `mesh`, positive if its triangles face outwards
+pub(crate) fn signed_volume(mesh: &Mesh) -> f32 {
+    mesh.triangles().map(|[v0, v1, v2]| v0.dot(v1.cross(v2))).sum::<f32>() / 6.0
+}
+
+/// Number of triangles around each edge of `mesh`
+///
+/// Vertices are identified by index, or by position in a triangle soup.
+pub(crate) fn edge_counts(mesh: &Mesh) -> HashMap<(u32, u32), usize> {
+    let mut ids = HashMap::new();
+    let id = |v: u32, ids: &mut HashMap<[u32; 3], u32>| match mesh.indices {
+        Some(_) => v,
+        None => {
+            let key = mesh.positions[v as usize].map(f32::to_bits);
+            let next = ids.len() as u32;
+            *ids.entry(key).or_insert(next)
+        }
+    };
+
+    let mut counts = HashMap::new();
+    for t in mesh.triangle_indices() {
+        let t = t.map(|v| id(v, &mut ids));
+        for i in 0..3 {
+            let (a, b) = (t[i], t[(i + 1) % 3]);
+            *counts.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// Triangle soup of the triangles of `mesh`, welding its vertices by position
+/// in [`edge_counts`]
+pub(crate) fn soup(mesh: &Mesh) -> Mesh {
+    let positions: Vec<[f32; 3]> = mesh.triangles().flatten().map(Into::into).collect();
+    Mesh::new(positions.clone(), positions)
+}
+
+/// Number of edges of `mesh` with a single triangle, see [`edge_counts`]
+pub(crate) fn boundary_edges(mesh: &Mesh) -> usize {
+    edge_counts(mesh).values().filter(|&&n| n == 1).count()
+}
+
+/// The 24 rotations of a cube as permutations of its corners, corner `c`
+/// being at (c & 1, c >> 1 & 1, c >> 2)
+pub(crate) fn cube_rotations() -> Vec<Vec<usize>> {
+    let rotate = |c: usize, axis: usize| {
+        // Quarter turn about `axis`, moving the next axis onto the one after
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let bit = |c: usize, axis: usize| (c >> axis) & 1;
+        (c & (1 << axis)) | ((1 - bit(c, v)) << u) | (bit(c, u) << v)
+    };
+
+    let mut rotations = vec![(0..8).collect::<Vec<_>>()];
+    let mut i = 0;
+    while i < rotations.len() {
+        for axis in [0, 2] {
+            let rotation: Vec<usize> = rotations[i].iter().map(|&c| rotate(c, axis)).collect();
+            if !rotations.contains(&rotation) {
+                rotations.push(rotation);
+            }
+        }
+        i += 1;
+    }
+    rotations
+}
+
+/// Number of classes of sign configurations of `shape` whose loops of
+/// crossed edges, see [`CellShape::loops`], are the same up to one of
+/// `symmetries`, given as permutations of the samples
+///
+/// Ambiguous faces connect the positive samples, as in the tables of
+/// [`CellShape::table`] used for meshing. A configuration and its inverse share a class if the inverse
+/// gives the same loops.
+pub(crate) fn loop_classes(shape: &CellShape, symmetries: &[Vec<usize>]) -> usize {
+    let classes: HashSet<_> = (0..shape.num_cases())
+        .map(|case| {
+            let loops = shape.loops(case, |_| false);
+            symmetries
+                .iter()
+                .map(|symmetry| {
+                    loops
+                        .iter()
+                        .map(|edges| {
+                            edges
+                                .iter()
+                                .map(|&e| {
+                                    let (a, b) = shape.edges()[e as usize];
+                                    let (a, b) = (symmetry[a], symmetry[b]);
+                                    (a.min(b), a.max(b))
+                                })
+                                .collect::<BTreeSet<_>>()
+                        })
+                        .collect::<BTreeSet<_>>()
+                })
+                .min()
+                .unwrap()
+        })
+        .collect();
+    classes.len()
+}
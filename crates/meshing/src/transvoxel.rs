@@ -0,0 +1,438 @@
+//! Transvoxel, E Lengyel, Transition Cells for Dynamic Multiresolution
+//! Marching Cubes
+//!
+//! The published regular and transition cell tables are not used. Both are
+//! derived from the cell shapes with [`CellShape::table`] when first used,
+//! resolving every ambiguous face the same way so that regular and
+//! transition cells agree on the faces they share. The cases fall into the
+//! published 18 regular and 73 transition classes, see the
+//! `cases_fall_into_published_classes` test, but the triangles of a class
+//! may differ from the published ones.
+
+use std::ops::BitOr;
+use std::sync::OnceLock;
+
+use glam::Vec3;
+
+use crate::cell_contour::{CellShape, CellTable};
+use crate::degenerate::face_normal;
+use crate::field::ScalarField;
+use crate::mesh::Mesh;
+use crate::options::MeshingOptions;
+use crate::Chunk;
+
+/// Width of the transition cells, relative to the cell size of the chunk
+const TRANSITION_WIDTH: f32 = 0.5;
+
+/// Corner offsets of a regular cell, x varying fastest
+const REGULAR_CORNERS: [(usize, usize, usize); 8] = [
+    (0, 0, 0),
+    (1, 0, 0),
+    (0, 1, 0),
+    (1, 1, 0),
+    (0, 0, 1),
+    (1, 0, 1),
+    (0, 1, 1),
+    (1, 1, 1),
+];
+
+/// Number of full resolution samples on the face of a transition cell
+const TRANSITION_SAMPLES: usize = 9;
+
+/// Full resolution sample each low resolution corner (samples 9 to 12) shares its value with
+const TRANSITION_CORNERS: [usize; 4] = [0, 2, 6, 8];
+
+/// Regular cell shape and lookup table
+///
+/// This table is used instead of `EDGE_TABLE`/`TRI_TABLE` as it has to
+/// resolve ambiguous faces the same way as the transition cells.
+fn regular_table() -> &'static (CellShape, CellTable) {
+    static TABLE: OnceLock<(CellShape, CellTable)> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let shape = CellShape::cube(&REGULAR_CORNERS);
+        let table = shape.table(false);
+        (shape, table)
+    })
+}
+
+/// Transition cell shape and lookup table
+///
+/// Samples 0 to 8 are on the full resolution face, in a 3x3 grid with the
+/// first face axis varying fastest. Samples 9 to 12 are the corners of the
+/// half resolution face, sharing their values with samples 0, 2, 6 and 8. In
+/// cell coordinates the full resolution face is at depth 0 and the half
+/// resolution face at depth 1.
+fn transition_table() -> &'static (CellShape, CellTable) {
+    static TABLE: OnceLock<(CellShape, CellTable)> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let s = |i: usize, j: usize| i + 3 * j;
+
+        let mut positions = Vec::new();
+        for j in 0..3 {
+            for i in 0..3 {
+                positions.push(Vec3::new(i as f32 * 0.5, j as f32 * 0.5, 0.0));
+            }
+        }
+        for j in 0..2 {
+            for i in 0..2 {
+                positions.push(Vec3::new(i as f32, j as f32, 1.0));
+            }
+        }
+
+        let mut sign_source: Vec<usize> = (0..TRANSITION_SAMPLES).collect();
+        sign_source.extend(TRANSITION_CORNERS);
+
+        let mut edges = Vec::new();
+        for j in 0..3 {
+            for i in 0..2 {
+                edges.push((s(i, j), s(i + 1, j)));
+                edges.push((s(j, i), s(j, i + 1)));
+            }
+        }
+        edges.extend([(9, 10), (11, 12), (9, 11), (10, 12)]);
+
+        let mut faces = Vec::new();
+        for j in 0..2 {
+            for i in 0..2 {
+                faces.push(vec![s(i, j), s(i + 1, j), s(i + 1, j + 1), s(i, j + 1)]);
+            }
+        }
+        faces.push(vec![9, 10, 12, 11]);
+        faces.push(vec![s(0, 0), s(1, 0), s(2, 0), 10, 9]);
+        faces.push(vec![s(0, 2), s(1, 2), s(2, 2), 12, 11]);
+        faces.push(vec![s(0, 0), s(0, 1), s(0, 2), 11, 9]);
+        faces.push(vec![s(2, 0), s(2, 1), s(2, 2), 12, 10]);
+
+        let shape = CellShape::new(&positions, sign_source, edges, faces);
+        let table = shape.table(false);
+        (shape, table)
+    })
+}
+
+/// Set of chunk faces bordering a chunk with twice the resolution
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TransitionFaces(u8);
+
+impl TransitionFaces {
+    pub const NONE: Self = Self(0);
+    pub const NEG_X: Self = Self(1 << 0);
+    pub const POS_X: Self = Self(1 << 1);
+    pub const NEG_Y: Self = Self(1 << 2);
+    pub const POS_Y: Self = Self(1 << 3);
+    pub const NEG_Z: Self = Self(1 << 4);
+    pub const POS_Z: Self = Self(1 << 5);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Face on the negative (`high == false`) or positive side of `axis`
+    fn face(axis: usize, high: bool) -> Self {
+        Self(1 << (2 * axis + high as usize))
+    }
+}
+
+impl BitOr for TransitionFaces {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Implements E Lengyel, Transition Cells for Dynamic Multiresolution Marching Cubes
+///
+/// Meshes `chunk` with cells spanning `2^lod` samples of `field`. Faces listed
+/// in `transition_faces` border a chunk of level `lod - 1`, and are stitched
+/// to it with transition cells sampling the face at full resolution. The
+/// regular cells next to those faces are shrunk to make room for the
+/// transition cells.
+///
+/// All chunks, including the ones at level 0, must be meshed with this
+/// function for the result to be watertight. `field` must cover the chunk
+/// including its far faces. Positions are in grid coordinates of `field`, see
+/// [`Chunk`].
+///
+/// # Panics
+///
+/// If `transition_faces` is not empty at level 0.
+pub fn transvoxel<F: ScalarField + ?Sized>(
+    field: &F,
+    chunk: &Chunk,
+    lod: u32,
+    transition_faces: TransitionFaces,
+    iso_level: f32,
+) -> Mesh {
+    extract(field, chunk, lod, transition_faces, iso_level, Vec3::ONE)
+}
+
+/// [`transvoxel`] with the given options
+///
+/// Without a geometry set, positions stay in grid coordinates of `field`
+/// rather than being normalized, so that chunks meshed separately line up.
+pub fn transvoxel_with<F: ScalarField + ?Sized>(
+    field: &F,
+    chunk: &Chunk,
+    lod: u32,
+    transition_faces: TransitionFaces,
+    options: MeshingOptions,
+) -> Mesh {
+    let grid = options.geometry.unwrap_or_default();
+    let mesh = extract(field, chunk, lod, transition_faces, options.iso_level, grid.spacing);
+    options.finish(mesh, &grid)
+}
+
+/// Transvoxel with vertices scaled by `scale`
+fn extract<F: ScalarField + ?Sized>(
+    field: &F,
+    chunk: &Chunk,
+    lod: u32,
+    transition_faces: TransitionFaces,
+    iso_level: f32,
+    scale: Vec3,
+) -> Mesh {
+    assert!(
+        lod > 0 || transition_faces == TransitionFaces::NONE,
+        "transition faces require a level of detail above 0"
+    );
+
+    let stride = 1 << lod;
+    let dims = field.dims();
+    let mesher = Mesher {
+        field,
+        iso_level,
+        origin: chunk.origin,
+        size: [0, 1, 2].map(|i| {
+            chunk.size[i].min(dims[i].saturating_sub(chunk.origin[i] + 1) / stride)
+        }),
+        stride,
+        transition_faces,
+    };
+
+    let mut mesh_positions = Vec::<[f32;3]>::new();
+    let mut mesh_normals = Vec::<[f32;3]>::new();
+    let mut emit = |v0: Vec3, v1: Vec3, v2: Vec3| {
+        let (v0, v1, v2) = (v0 * scale, v1 * scale, v2 * scale);
+        mesh_positions.push(v0.into());
+        mesh_positions.push(v1.into());
+        mesh_positions.push(v2.into());
+
+        let normal = face_normal(v0, v1, v2);
+        mesh_normals.push(normal.into());
+        mesh_normals.push(normal.into());
+        mesh_normals.push(normal.into());
+    };
+
+    mesher.regular_cells(&mut emit);
+    for axis in 0..3 {
+        for high in [false, true] {
+            if transition_faces.contains(TransitionFaces::face(axis, high)) {
+                mesher.transition_cells(axis, high, &mut emit);
+            }
+        }
+    }
+
+    Mesh::new(mesh_positions, mesh_normals)
+}
+
+struct Mesher<'a, F: ?Sized> {
+    field: &'a F,
+    iso_level: f32,
+    origin: [usize; 3],
+    /// Number of cells, clamped to the field
+    size: [usize; 3],
+    /// Number of samples per cell
+    stride: usize,
+    transition_faces: TransitionFaces,
+}
+
+impl<F: ScalarField + ?Sized> Mesher<'_, F> {
+    /// Sample at `p` relative to the iso level
+    fn sample(&self, p: [usize; 3]) -> f32 {
+        self.field.sample_relative(p[0], p[1], p[2], self.iso_level)
+    }
+
+    /// Crossing on the edge between samples `a` and `b`
+    ///
+    /// Always interpolates from the lower sample so that chunks sharing the
+    /// edge compute the exact same position.
+    fn crossing(&self, a: [usize; 3], b: [usize; 3]) -> Vec3 {
+        let (a, b) = if (a[2], a[1], a[0]) < (b[2], b[1], b[0]) { (a, b) } else { (b, a) };
+        let to_vec = |p: [usize; 3]| Vec3::new(p[0] as f32, p[1] as f32, p[2] as f32);
+
+        let (va, vb) = (self.sample(a), self.sample(b));
+        let t = -va / (vb - va);
+        to_vec(a) + t * (to_vec(b) - to_vec(a))
+    }
+
+    /// Moves a vertex of the cells next to a transition face away from the face
+    fn displace(&self, mut p: Vec3) -> Vec3 {
+        let cell_size = self.stride as f32;
+        let width = TRANSITION_WIDTH * cell_size;
+
+        for axis in 0..3 {
+            let u = p[axis] - self.origin[axis] as f32;
+            let length = (self.size[axis] * self.stride) as f32;
+
+            if self.transition_faces.contains(TransitionFaces::face(axis, false)) && u < cell_size {
+                p[axis] += (1.0 - u / cell_size) * width;
+            }
+            if self.transition_faces.contains(TransitionFaces::face(axis, true)) && u > length - cell_size {
+                p[axis] -= (1.0 - (length - u) / cell_size) * width;
+            }
+        }
+        p
+    }
+
+    fn regular_cells(&self, emit: &mut impl FnMut(Vec3, Vec3, Vec3)) {
+        let (shape, table) = regular_table();
+
+        for z in 0..self.size[2] {
+            for y in 0..self.size[1] {
+                for x in 0..self.size[0] {
+                    let corners = REGULAR_CORNERS.map(|c| [
+                        self.origin[0] + (x + c.0) * self.stride,
+                        self.origin[1] + (y + c.1) * self.stride,
+                        self.origin[2] + (z + c.2) * self.stride,
+                    ]);
+
+                    let mut case = 0;
+                    for (i, &corner) in corners.iter().enumerate() {
+                        case |= ((self.sample(corner) > 0.0) as usize) << i;
+                    }
+
+                    let vertex = |e: u8| {
+                        let (a, b) = shape.edges()[e as usize];
+                        self.displace(self.crossing(corners[a], corners[b]))
+                    };
+                    for tri in &table.triangles[case] {
+                        emit(vertex(tri[0]), vertex(tri[1]), vertex(tri[2]));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Transition cells along the face on the `high` side of `axis`
+    fn transition_cells(&self, axis: usize, high: bool, emit: &mut impl FnMut(Vec3, Vec3, Vec3)) {
+        let (shape, table) = transition_table();
+
+        // Face axes of the cell, with the depth axis pointing into the chunk
+        // the (u, v, depth) frame is right-handed on the negative faces
+        let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+        let half = self.stride / 2;
+
+        for v in 0..self.size[v_axis] {
+            for u in 0..self.size[u_axis] {
+                let mut samples = [[0; 3]; TRANSITION_SAMPLES + 4];
+                for (i, sample) in samples.iter_mut().enumerate() {
+                    let (su, sv) = if i < TRANSITION_SAMPLES {
+                        (i % 3, i / 3)
+                    } else {
+                        let corner = TRANSITION_CORNERS[i - TRANSITION_SAMPLES];
+                        (corner % 3, corner / 3)
+                    };
+                    sample[u_axis] = self.origin[u_axis] + u * self.stride + su * half;
+                    sample[v_axis] = self.origin[v_axis] + v * self.stride + sv * half;
+                    sample[axis] = self.origin[axis] + high as usize * self.size[axis] * self.stride;
+                }
+
+                let mut case = 0;
+                for (i, &sample) in samples[..TRANSITION_SAMPLES].iter().enumerate() {
+                    case |= ((self.sample(sample) > 0.0) as usize) << i;
+                }
+
+                let vertex = |e: u8| {
+                    let (a, b) = shape.edges()[e as usize];
+                    let p = self.crossing(samples[a], samples[b]);
+                    if a < TRANSITION_SAMPLES {
+                        p
+                    } else {
+                        // Half resolution face, shared with the regular cells
+                        self.displace(p)
+                    }
+                };
+                for tri in &table.triangles[case] {
+                    if high {
+                        emit(vertex(tri[0]), vertex(tri[2]), vertex(tri[1]));
+                    } else {
+                        emit(vertex(tri[0]), vertex(tri[1]), vertex(tri[2]));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+    use crate::test_util::{boundary_edges, cube_rotations, loop_classes, sphere};
+
+    /// Sphere meshed by a level 0 and a level 1 chunk meeting at x = 16
+    fn two_levels(transition: bool) -> Mesh {
+        let field = sphere([33, 33, 33], Vec3::splat(16.0), 10.3);
+        let faces = if transition { TransitionFaces::NEG_X } else { TransitionFaces::NONE };
+
+        let mut mesh = transvoxel(&field, &Chunk::new([0, 0, 0], [16, 32, 32]), 0, TransitionFaces::NONE, 0.0);
+        mesh.merge(&transvoxel(&field, &Chunk::new([16, 0, 0], [8, 16, 16]), 1, faces, 0.0));
+        mesh
+    }
+
+    #[test]
+    fn transition_face_is_watertight() {
+        let mesh = two_levels(true);
+        assert!(!mesh.is_empty());
+        assert_eq!(boundary_edges(&mesh), 0);
+    }
+
+    #[test]
+    fn levels_crack_without_transition_cells() {
+        assert!(boundary_edges(&two_levels(false)) > 0);
+    }
+
+    #[test]
+    fn transition_on_positive_face_is_watertight() {
+        let field = sphere([33, 33, 33], Vec3::splat(16.0), 10.3);
+
+        let mut mesh = transvoxel(&field, &Chunk::new([0, 0, 0], [8, 16, 16]), 1, TransitionFaces::POS_X, 0.0);
+        mesh.merge(&transvoxel(&field, &Chunk::new([16, 0, 0], [16, 32, 32]), 0, TransitionFaces::NONE, 0.0));
+        assert_eq!(boundary_edges(&mesh), 0);
+    }
+
+    /// Symmetries of the square faces of a transition cell as permutations of
+    /// its samples
+    fn square_symmetries() -> Vec<Vec<usize>> {
+        (0..8)
+            .map(|symmetry| {
+                // Mirrored, then rotated by quarter turns, on an n x n grid
+                let map = |[mut i, mut j]: [usize; 2], n: usize| {
+                    if symmetry >= 4 {
+                        i = n - 1 - i;
+                    }
+                    for _ in 0..symmetry % 4 {
+                        [i, j] = [n - 1 - j, i];
+                    }
+                    [i, j]
+                };
+
+                let full = (0..TRANSITION_SAMPLES).map(|s| map([s % 3, s / 3], 3)).map(|[i, j]| i + 3 * j);
+                let half = (0..4).map(|c| map([c % 2, c / 2], 2)).map(|[i, j]| TRANSITION_SAMPLES + i + 2 * j);
+                full.chain(half).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cases_fall_into_published_classes() {
+        let (regular, _) = regular_table();
+        assert_eq!(regular.num_cases(), 256);
+        assert_eq!(loop_classes(regular, &cube_rotations()), 18);
+
+        let (transition, _) = transition_table();
+        assert_eq!(transition.num_cases(), 512);
+        assert_eq!(loop_classes(transition, &square_symmetries()), 73);
+    }
+}